@@ -1,17 +1,36 @@
-use crate::{config::Config, state::State, RotateLogWriter};
+use crate::{
+    cleanup::remove_old_log_files,
+    config::Config,
+    drop_counter::DropCounter,
+    naming::{validate_subdirectory, DefaultNaming, FilenameTemplate},
+    policy::default_policy,
+    postrotate::PostrotateCommand,
+    state::State,
+    state_handle::{AsyncHandle, StateHandle, SyncHandle},
+    Clock, Compression, NamingScheme, RotateLogWriter, RotationEvent, RotationPeriod,
+    RotationPolicy, RotationTimezone, WriteMode,
+};
+use chrono::NaiveTime;
 use flexi_logger::{default_format, FlexiLoggerError, FormatFunction, LevelFilter};
 use std::{
+    ffi::OsString,
+    io::{Error as IoError, ErrorKind},
     path::{Path, PathBuf},
-    sync::Mutex,
+    sync::Arc,
+    time::Duration,
 };
 
 /// Builder for [`RotateLogWriter`].
 pub struct RotateLogWriterBuilder {
     basename: Option<String>,
     discriminant: Option<String>,
+    o_template: Option<String>,
+    o_rotation_policy: Option<Box<dyn RotationPolicy>>,
+    o_naming_scheme: Option<Box<dyn NamingScheme>>,
     config: Config,
     format: FormatFunction,
     max_log_level: LevelFilter,
+    write_mode: WriteMode,
 }
 
 impl Default for RotateLogWriterBuilder {
@@ -19,9 +38,13 @@ impl Default for RotateLogWriterBuilder {
         Self {
             basename: None,
             discriminant: None,
+            o_template: None,
+            o_rotation_policy: None,
+            o_naming_scheme: None,
             config: Config::default(),
             format: default_format,
             max_log_level: LevelFilter::Trace,
+            write_mode: WriteMode::default(),
         }
     }
 }
@@ -81,6 +104,89 @@ impl RotateLogWriterBuilder {
         self
     }
 
+    /// Specifies a template for the log file names, like `{basename}-{date:%Y-%m-%d}.log`,
+    /// instead of the default `{basename}_r{date}.{suffix}`.
+    ///
+    /// The template can contain these placeholders:
+    ///
+    /// - `{basename}`: the basename, see [`basename`](Self::basename)
+    /// - `{discriminant}`: the discriminant, see [`discriminant`](Self::discriminant)
+    /// - `{date}`: the start of the period, in the default format of the rotation period
+    /// - `{date:<format>}`: the start of the period, in the given `strftime` format
+    /// - `{seq}`: the sequence number within the period, starting with 0
+    /// - `{pid}`: the process id
+    /// - `{hostname}`: the hostname
+    ///
+    /// The template must contain a date, unless the date is in the
+    /// [`subdirectory`](Self::subdirectory), and it must contain `{seq}` if
+    /// [`max_file_size`](Self::max_file_size), [`new_file_on_start`](Self::new_file_on_start)
+    /// or a [`rotation_policy`](Self::rotation_policy) is used. Existing log files are recognized
+    /// with the same template, so the date format should contain everything that
+    /// distinguishes the periods. The [`suffix`](Self::suffix) is not used with a template.
+    #[inline]
+    #[must_use]
+    pub fn filename_template<S: Into<String>>(mut self, template: S) -> Self {
+        self.o_template = Some(template.into());
+        self
+    }
+
+    /// Starts a new numbered file with each start of the program, like `foo_r2021-03-28.3.log`,
+    /// rather than appending to the file of the current period.
+    ///
+    /// The sequence numbers that are already used are found by scanning the log directory
+    /// when the writer is built. With a [`filename_template`](Self::filename_template),
+    /// the template must contain `{seq}`.
+    #[inline]
+    #[must_use]
+    pub const fn new_file_on_start(mut self, new_file_on_start: bool) -> Self {
+        self.config.new_file_on_start = new_file_on_start;
+        self
+    }
+
+    /// Specifies a custom naming scheme for the log files, see [`NamingScheme`].
+    ///
+    /// The scheme replaces the default naming, so that the [`suffix`](Self::suffix),
+    /// [`filename_template`](Self::filename_template) and [`subdirectory`](Self::subdirectory)
+    /// are not used for the log files, except for the fixed file of
+    /// [`use_current_file`](Self::use_current_file).
+    #[inline]
+    #[must_use]
+    pub fn naming_scheme<N: NamingScheme + 'static>(mut self, naming_scheme: N) -> Self {
+        self.o_naming_scheme = Some(Box::new(naming_scheme));
+        self
+    }
+
+    /// Always writes to the same file `{basename}.{suffix}` in the log directory, which is
+    /// renamed to its dated name at rotation, before a fresh file is opened.
+    ///
+    /// This helps tools that can only follow a fixed path, and do not follow symlinks.
+    /// A current file that is left over from an earlier period, e.g. by a previous run
+    /// of the program, is renamed at startup as well. Its period is taken from its
+    /// modification time, which is set to the time of the last write on shutdown.
+    #[inline]
+    #[must_use]
+    pub const fn use_current_file(mut self, use_current_file: bool) -> Self {
+        self.config.filename_config.use_current_file = use_current_file;
+        self
+    }
+
+    /// Nests the log files in subdirectories of the log directory that are named after
+    /// the start of their period, with the given `strftime` format.
+    ///
+    /// E.g. `"%Y/%m/%d"` produces paths like `logs/2021/03/28/foo_r2021-03-28.log`,
+    /// and `"%Y-%m"` together with the filename template `"{basename}_r{date:%d}.log"`
+    /// produces paths like `logs/2021-03/foo_r28.log`. Use `/` as the separator
+    /// on all platforms.
+    ///
+    /// Missing subdirectories are created when a new log file is opened, and subdirectories
+    /// that become empty by the removal of old log files are removed.
+    #[inline]
+    #[must_use]
+    pub fn subdirectory<S: Into<String>>(mut self, format: S) -> Self {
+        self.config.filename_config.o_subdirectory = Some(format.into());
+        self
+    }
+
     /// The specified String will be used on linux systems to create in the current folder
     /// a symbolic link to the current log file.
     #[inline]
@@ -121,12 +227,206 @@ impl RotateLogWriterBuilder {
         self
     }
 
+    /// Specifies how often a new log file is started. The default is [`RotationPeriod::Daily`].
+    ///
+    /// The rotation never goes back to an older period. If the clock is set back,
+    /// or a repeated hour is passed at the end of daylight saving time,
+    /// the log lines are written to the current file until time catches up.
+    #[inline]
+    #[must_use]
+    pub const fn rotate(mut self, rotation: RotationPeriod) -> Self {
+        self.config.rotation = rotation;
+        self
+    }
+
+    /// Registers a callback that is invoked after each rotation, e.g. to notify a log shipper
+    /// that a file is finished.
+    ///
+    /// The callback runs on the housekeeping thread, not on the logging threads,
+    /// after the closed file was compressed and before old log files are removed.
+    /// It should not log through this writer.
+    #[inline]
+    #[must_use]
+    pub fn on_rotate(mut self, on_rotate: Box<dyn Fn(RotationEvent) + Send + Sync>) -> Self {
+        self.config.o_on_rotate = Some(on_rotate);
+        self
+    }
+
+    /// Runs an external command after each rotation, like `postrotate` of logrotate.
+    ///
+    /// `argv` contains the program and its arguments, which are not interpreted by a shell.
+    /// Arguments that are exactly `{path}` are replaced by the path of the rotated file,
+    /// which is also passed in the environment variable `ROTATED_LOG_FILE`.
+    /// If the file was compressed, this is the path of the compressed file.
+    ///
+    /// The command runs on the housekeeping thread, so that logging is not blocked.
+    /// It is killed if it does not finish within `timeout`. Failures and the first 4 KiB
+    /// of the output of the command on stderr are reported to stderr.
+    #[must_use]
+    pub fn postrotate_command<I, S>(mut self, argv: I, timeout: Duration) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<OsString>,
+    {
+        self.config.o_postrotate_command = Some(PostrotateCommand {
+            argv: argv.into_iter().map(Into::into).collect(),
+            timeout,
+        });
+        self
+    }
+
+    /// Specifies a custom policy for when to rotate, see the [`policy`](crate::policy) module.
+    ///
+    /// It replaces the default policy, which rotates when a new period of
+    /// [`rotate`](Self::rotate) begins, or when [`max_file_size`](Self::max_file_size)
+    /// is reached. The rotation period still determines the date in the file names.
+    ///
+    /// A [`filename_template`](Self::filename_template) must then contain `{seq}`,
+    /// otherwise [`try_build`](Self::try_build) fails.
+    #[inline]
+    #[must_use]
+    pub fn rotation_policy(mut self, policy: Box<dyn RotationPolicy>) -> Self {
+        self.o_rotation_policy = Some(policy);
+        self
+    }
+
+    /// Specifies the time of day at which daily, weekly and monthly rotation happens,
+    /// instead of midnight.
+    ///
+    /// The date in the file name is then the business day that the file covers.
+    /// For example, with `rotate_at(NaiveTime::from_hms_opt(4, 0, 0).unwrap())`,
+    /// `foo_r2021-03-28.log` covers the time from 2021-03-28 04:00 to 2021-03-29 04:00.
+    #[inline]
+    #[must_use]
+    pub const fn rotate_at(mut self, time: NaiveTime) -> Self {
+        self.config.rotate_at = time;
+        self
+    }
+
+    /// Makes the rotation decision based on the timestamp of each log record,
+    /// rather than on the clock at the time the record is written.
+    ///
+    /// A record stamped 23:59:59.999 that is written a few milliseconds after midnight
+    /// then usually still goes to that day's file. But the rotation never goes back
+    /// to an older period: if a record stamped after midnight was written first, e.g. by
+    /// another thread, or if [`rotate_on_timer`](Self::rotate_on_timer) has already rotated
+    /// by the clock, the late record goes to the next day's file.
+    #[inline]
+    #[must_use]
+    pub const fn rotate_by_record_time(mut self, rotate_by_record_time: bool) -> Self {
+        self.config.rotate_by_record_time = rotate_by_record_time;
+        self
+    }
+
+    /// Rotates right at the end of each period, even if nothing is logged.
+    ///
+    /// By default, the rotation only happens when the first log line of the new period
+    /// is written, so that the file of a quiet period may stay open for a long time.
+    /// With this option, a timer thread closes the file at the period boundary,
+    /// does the housekeeping for it, and opens the file for the new period.
+    #[inline]
+    #[must_use]
+    pub const fn rotate_on_timer(mut self, rotate_on_timer: bool) -> Self {
+        self.config.rotate_on_timer = rotate_on_timer;
+        self
+    }
+
+    /// Specifies the timezone in which the rotation boundaries, the dates in the file names
+    /// and the times in reports of dropped records are computed.
+    /// The default is [`RotationTimezone::Local`].
+    #[inline]
+    #[must_use]
+    pub const fn timezone(mut self, timezone: RotationTimezone) -> Self {
+        self.config.timezone = timezone;
+        self
+    }
+
+    /// Activates size-based rotation in addition to the time-based rotation.
+    ///
+    /// When the current log file reaches the specified size in bytes, a new file with
+    /// the next sequence number is opened, like `foo_r2021-03-28.1.log`.
+    /// The sequence starts again with `foo_r2021-03-28.log` when the next period begins.
+    #[inline]
+    #[must_use]
+    pub const fn max_file_size(mut self, bytes: u64) -> Self {
+        self.config.o_max_file_size = Some(bytes);
+        self
+    }
+
+    /// Keeps only the specified number of log files, including the current one.
+    ///
+    /// After each rotation, older log files of this writer are deleted from the log folder.
+    /// Other files in the folder are not touched. By default, no files are deleted.
+    ///
+    /// `n` must be at least 1, otherwise [`try_build`](Self::try_build) fails.
+    #[inline]
+    #[must_use]
+    pub const fn keep_files(mut self, n: usize) -> Self {
+        self.config.o_keep_files = Some(n);
+        self
+    }
+
+    /// Deletes log files whose period ended longer ago than the specified duration.
+    ///
+    /// The check is done when the writer is built and after each rotation.
+    /// Other files in the log folder are not touched. By default, no files are deleted.
+    #[inline]
+    #[must_use]
+    pub const fn max_age(mut self, max_age: Duration) -> Self {
+        self.config.o_max_age = Some(max_age);
+        self
+    }
+
+    /// Limits the total size in bytes of all log files of this writer.
+    ///
+    /// When the first log file is opened and after each rotation, the oldest log files
+    /// are deleted until the total size is no longer above the limit. The current log file is never deleted.
+    /// By default, no files are deleted.
+    #[inline]
+    #[must_use]
+    pub const fn max_total_size(mut self, bytes: u64) -> Self {
+        self.config.o_max_total_size = Some(bytes);
+        self
+    }
+
+    /// Compresses each log file with the specified compression after it was rotated,
+    /// and removes the original.
+    ///
+    /// The compression is done in a background thread, so that logging is not blocked.
+    /// The retention options treat a compressed file like the original log file.
+    #[inline]
+    #[must_use]
+    pub const fn compress_rotated(mut self, compression: Compression) -> Self {
+        self.config.o_compression = Some(compression);
+        self
+    }
+
+    /// Specifies whether the log lines are written directly by the logging threads,
+    /// or by a dedicated writer thread. The default is [`WriteMode::Direct`].
+    #[inline]
+    #[must_use]
+    pub const fn write_mode(mut self, write_mode: WriteMode) -> Self {
+        self.write_mode = write_mode;
+        self
+    }
+
+    /// Makes the [`RotateLogWriter`] take the current time from the given clock,
+    /// rather than from the [`SystemClock`](crate::SystemClock).
+    ///
+    /// This is meant for testing, with a [`MockClock`](crate::MockClock).
+    #[inline]
+    #[must_use]
+    pub fn clock<C: Clock + 'static>(mut self, clock: C) -> Self {
+        self.config.clock = Arc::new(clock);
+        self
+    }
+
     /// Produces the [`RotateLogWriter`].
     pub fn try_build(mut self) -> Result<RotateLogWriter, FlexiLoggerError> {
         // make sure the folder exists or create it
         let p_directory = Path::new(&self.config.filename_config.directory);
-        std::fs::create_dir_all(&p_directory)?;
-        if !std::fs::metadata(&p_directory)?.is_dir() {
+        std::fs::create_dir_all(p_directory)?;
+        if !std::fs::metadata(p_directory)?.is_dir() {
             return Err(FlexiLoggerError::OutputBadDirectory);
         };
 
@@ -138,14 +438,110 @@ impl RotateLogWriterBuilder {
                 Path::new(&arg0).file_stem().unwrap(/*cannot fail*/).to_string_lossy().to_string();
         }
 
+        if self.config.o_keep_files == Some(0) {
+            return Err(IoError::new(
+                ErrorKind::InvalidInput,
+                "keep_files must be at least 1, as the current log file is always kept",
+            )
+            .into());
+        }
+
+        if let Some(postrotate_command) = &self.config.o_postrotate_command {
+            if postrotate_command.argv.is_empty() {
+                return Err(IoError::new(
+                    ErrorKind::InvalidInput,
+                    "the postrotate command must not be empty",
+                )
+                .into());
+            }
+        }
+
+        if let Some(subdirectory) = &self.config.filename_config.o_subdirectory {
+            validate_subdirectory(subdirectory).map_err(|e| {
+                IoError::new(
+                    ErrorKind::InvalidInput,
+                    format!("invalid subdirectory: {}", e),
+                )
+            })?;
+        }
+
+        if let Some(template) = self.o_template {
+            let template = FilenameTemplate::parse(
+                &template,
+                &self.config.filename_config.file_basename,
+                self.discriminant.as_deref(),
+                self.config.rotation,
+            )
+            .map_err(|e| {
+                IoError::new(
+                    ErrorKind::InvalidInput,
+                    format!("invalid filename template: {}", e),
+                )
+            })?;
+            if !template.has_date() && self.config.filename_config.o_subdirectory.is_none() {
+                return Err(IoError::new(
+                    ErrorKind::InvalidInput,
+                    "the filename template must contain {date} when no subdirectory is used",
+                )
+                .into());
+            }
+            if self.config.o_max_file_size.is_some() && !template.has_seq() {
+                return Err(IoError::new(
+                    ErrorKind::InvalidInput,
+                    "the filename template must contain {seq} when max_file_size is used",
+                )
+                .into());
+            }
+            if self.o_rotation_policy.is_some() && !template.has_seq() {
+                return Err(IoError::new(
+                    ErrorKind::InvalidInput,
+                    "the filename template must contain {seq} when a rotation policy is used",
+                )
+                .into());
+            }
+            if self.config.new_file_on_start && !template.has_seq() {
+                return Err(IoError::new(
+                    ErrorKind::InvalidInput,
+                    "the filename template must contain {seq} when new_file_on_start is used",
+                )
+                .into());
+            }
+            self.config.filename_config.o_template = Some(template);
+        }
+
         if let Some(discriminant) = self.discriminant {
             self.config.filename_config.file_basename += &format!("_{}", discriminant);
         }
 
+        let filename_config = &self.config.filename_config;
+        let rotation = self.config.rotation;
+        self.config.naming = self
+            .o_naming_scheme
+            .unwrap_or_else(|| Box::new(DefaultNaming::new(filename_config.clone(), rotation)));
+
+        remove_old_log_files(&self.config, None);
+
+        let config = Arc::new(self.config);
+        let drop_counter = Arc::new(DropCounter::new(Arc::clone(&config.clock)));
+        let policy = self
+            .o_rotation_policy
+            .unwrap_or_else(|| default_policy(config.rotation, config.o_max_file_size));
+        let state = State::try_new(Arc::clone(&config), Arc::clone(&drop_counter), policy)?;
+        let state_handle = match self.write_mode {
+            WriteMode::Direct => StateHandle::Sync(SyncHandle::start(state)?),
+            WriteMode::Async { capacity, overflow } => StateHandle::Async(AsyncHandle::start(
+                state,
+                capacity,
+                overflow,
+                Arc::clone(&drop_counter),
+            )?),
+        };
+
         Ok(RotateLogWriter::new(
             self.format,
-            self.config.line_ending,
-            Mutex::new(State::new(self.config)),
+            config,
+            state_handle,
+            drop_counter,
             self.max_log_level,
         ))
     }
@@ -0,0 +1,75 @@
+use crate::{config::Config, log_files::list_log_files};
+use chrono::Duration;
+use std::{io::Result as IoResult, path::Path};
+
+/// Removes the log files that exceed the configured retention.
+///
+/// The currently active log file, if any, is never removed.
+pub(crate) fn remove_old_log_files(config: &Config, o_active: Option<&Path>) {
+    if config.o_keep_files.is_none()
+        && config.o_max_age.is_none()
+        && config.o_max_total_size.is_none()
+    {
+        return;
+    }
+    remove_old_log_files_impl(config, o_active).unwrap_or_else(|e| {
+        eprintln!("[flexi_logger] cleanup of old log files failed with {}", e);
+    });
+}
+
+fn remove_old_log_files_impl(config: &Config, o_active: Option<&Path>) -> IoResult<()> {
+    let mut log_files = list_log_files(config)?;
+    let mut total_size: u64 = log_files.iter().map(|log_file| log_file.size).sum();
+    if let Some(active) = o_active {
+        log_files.retain(|log_file| log_file.path != active);
+    }
+
+    let mut n_remove = 0;
+    if let Some(keep_files) = config.o_keep_files {
+        // the active file counts as one of the kept files
+        let n_keep = keep_files - usize::from(o_active.is_some());
+        n_remove = log_files.len().saturating_sub(n_keep);
+    }
+    if let Some(max_age) = config.o_max_age {
+        let o_cutoff = Duration::from_std(max_age)
+            .ok()
+            .and_then(|max_age| config.now().checked_sub_signed(max_age));
+        if let Some(cutoff) = o_cutoff {
+            let n_too_old = log_files
+                .iter()
+                .take_while(|log_file| config.rotation.period_end(log_file.period_start) <= cutoff)
+                .count();
+            n_remove = n_remove.max(n_too_old);
+        }
+    }
+    // without an active file, i.e. at startup, the quota is left to the first cleanup after
+    // the log file is opened, as the file that is about to be continued could be removed
+    if let (Some(max_total_size), Some(_)) = (config.o_max_total_size, o_active) {
+        let mut n_over_quota = 0;
+        for log_file in &log_files {
+            if total_size <= max_total_size {
+                break;
+            }
+            total_size -= log_file.size;
+            n_over_quota += 1;
+        }
+        n_remove = n_remove.max(n_over_quota);
+    }
+
+    for log_file in log_files.iter().take(n_remove) {
+        std::fs::remove_file(&log_file.path)?;
+        remove_empty_subdirectories(config, &log_file.path);
+    }
+    Ok(())
+}
+
+/// Removes the date-partitioned subdirectories of a removed log file that are empty now.
+fn remove_empty_subdirectories(config: &Config, path: &Path) {
+    let directory = &config.filename_config.directory;
+    for dir in path.ancestors().skip(1) {
+        // remove_dir fails if the directory is not empty
+        if dir == directory || !dir.starts_with(directory) || std::fs::remove_dir(dir).is_err() {
+            break;
+        }
+    }
+}
@@ -0,0 +1,66 @@
+use chrono::{DateTime, Duration, Utc};
+use std::sync::{Arc, Mutex};
+
+/// The source of the current time for all time decisions of a
+/// [`RotateLogWriter`](crate::RotateLogWriter), like when to rotate and
+/// which date goes into the file name.
+pub trait Clock: Send + Sync {
+    /// The current time.
+    fn now(&self) -> DateTime<Utc>;
+}
+
+/// The system clock. This is the default.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> DateTime<Utc> {
+        Utc::now()
+    }
+}
+
+/// A clock that only moves when it is told to, for testing.
+///
+/// Clones of a `MockClock` share the same time, so a test can keep a clone
+/// and move the time of a writer that was built with another clone.
+///
+/// ```rust
+/// use chrono::{Duration, TimeZone, Utc};
+/// use flexi_logger_rotate_writer::{Clock, MockClock};
+///
+/// let clock = MockClock::new(Utc.with_ymd_and_hms(2021, 3, 28, 23, 59, 0).unwrap());
+/// let handle = clock.clone();
+/// handle.advance(Duration::minutes(2));
+/// assert_eq!(clock.now(), Utc.with_ymd_and_hms(2021, 3, 29, 0, 1, 0).unwrap());
+/// ```
+#[derive(Clone, Debug)]
+pub struct MockClock {
+    now: Arc<Mutex<DateTime<Utc>>>,
+}
+
+impl MockClock {
+    /// Creates a clock that stands at the given time.
+    #[must_use]
+    pub fn new(now: DateTime<Utc>) -> Self {
+        Self {
+            now: Arc::new(Mutex::new(now)),
+        }
+    }
+
+    /// Sets the clock to the given time.
+    pub fn set(&self, now: DateTime<Utc>) {
+        *self.now.lock().unwrap() = now;
+    }
+
+    /// Moves the clock by the given duration, which may be negative.
+    pub fn advance(&self, duration: Duration) {
+        let mut now = self.now.lock().unwrap();
+        *now += duration;
+    }
+}
+
+impl Clock for MockClock {
+    fn now(&self) -> DateTime<Utc> {
+        *self.now.lock().unwrap()
+    }
+}
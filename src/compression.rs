@@ -0,0 +1,88 @@
+use flate2::write::GzEncoder;
+use std::{
+    ffi::OsString,
+    fs::{File, OpenOptions},
+    io::{BufWriter, Result as IoResult, Write},
+    path::{Path, PathBuf},
+};
+
+/// The compression that is applied to log files after rotation.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Compression {
+    /// Compresses `foo_r2021-03-28.log` to `foo_r2021-03-28.log.gz`.
+    Gzip,
+    /// Compresses `foo_r2021-03-28.log` to `foo_r2021-03-28.log.zst`, with the given
+    /// compression level. Level 0 means the default level of zstd.
+    ///
+    /// Only available with the `zstd` feature.
+    #[cfg(feature = "zstd")]
+    Zstd(i32),
+}
+
+impl Compression {
+    const fn extension(self) -> &'static str {
+        match self {
+            Self::Gzip => "gz",
+            #[cfg(feature = "zstd")]
+            Self::Zstd(_) => "zst",
+        }
+    }
+
+    /// The path of the compressed version of the given log file.
+    pub(crate) fn archive_path(self, path: &Path) -> PathBuf {
+        let mut archive_path = OsString::from(path.as_os_str());
+        archive_path.push(".");
+        archive_path.push(self.extension());
+        PathBuf::from(archive_path)
+    }
+}
+
+/// Strips the extension of a compressed log file from the file name, if there is one.
+pub(crate) fn strip_archive_extension(file_name: &str) -> Option<&str> {
+    file_name
+        .strip_suffix(".gz")
+        .or_else(|| file_name.strip_suffix(".zst"))
+}
+
+/// Compresses the given log file and removes the original.
+///
+/// If a compressed file already exists, the new compressed data is appended to it,
+/// which still yields a valid archive.
+///
+/// Returns the path of the compressed file, or the original path if compressing failed.
+pub(crate) fn compress_log_file(path: &Path, compression: Compression) -> PathBuf {
+    match compress_log_file_impl(path, compression) {
+        Ok(()) => compression.archive_path(path),
+        Err(e) => {
+            eprintln!(
+                "[flexi_logger] compressing log file \"{}\" failed with {}",
+                path.display(),
+                e
+            );
+            path.to_path_buf()
+        }
+    }
+}
+
+fn compress_log_file_impl(path: &Path, compression: Compression) -> IoResult<()> {
+    let mut log_file = File::open(path)?;
+    let archive = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(compression.archive_path(path))?;
+    match compression {
+        Compression::Gzip => {
+            let mut encoder =
+                GzEncoder::new(BufWriter::new(archive), flate2::Compression::default());
+            std::io::copy(&mut log_file, &mut encoder)?;
+            encoder.finish()?.flush()?;
+        }
+        #[cfg(feature = "zstd")]
+        Compression::Zstd(level) => {
+            let mut encoder = zstd::Encoder::new(BufWriter::new(archive), level)?;
+            std::io::copy(&mut log_file, &mut encoder)?;
+            encoder.finish()?.flush()?;
+        }
+    }
+    std::fs::remove_file(path)
+}
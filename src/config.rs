@@ -1,34 +1,136 @@
-use crate::UNIX_LINE_ENDING;
-use std::path::PathBuf;
+use crate::{
+    naming::{DefaultNaming, FilenameTemplate, NamingScheme},
+    postrotate::PostrotateCommand,
+    Clock, Compression, RotationEvent, RotationPeriod, RotationTimezone, SystemClock,
+    UNIX_LINE_ENDING,
+};
+use chrono::{DateTime, NaiveDateTime, NaiveTime, Utc};
+use std::{path::PathBuf, sync::Arc, time::Duration};
+
+// how long the rotation timer sleeps at most before it checks the clock again
+const MAX_TIMER_WAIT: Duration = Duration::from_secs(1);
 
 #[derive(Clone)]
 pub struct FilenameConfig {
     pub(crate) directory: PathBuf,
     pub(crate) file_basename: String,
     pub(crate) suffix: String,
+    pub(crate) o_template: Option<FilenameTemplate>,
+    // strftime format of the subdirectories, like "%Y/%m/%d"
+    pub(crate) o_subdirectory: Option<String>,
+    // write to "{basename}.{suffix}", and rename it to the dated name at rotation
+    pub(crate) use_current_file: bool,
 }
 
 /// The immutable configuration of a `RotateLogWriter`.
 pub struct Config {
     pub(crate) print_message: bool,
     pub(crate) o_buffersize: Option<usize>,
+    pub(crate) o_max_file_size: Option<u64>,
+    pub(crate) o_keep_files: Option<usize>,
+    pub(crate) o_max_age: Option<Duration>,
+    pub(crate) o_max_total_size: Option<u64>,
+    pub(crate) o_compression: Option<Compression>,
+    pub(crate) o_on_rotate: Option<Box<dyn Fn(RotationEvent) + Send + Sync>>,
+    pub(crate) o_postrotate_command: Option<PostrotateCommand>,
     pub(crate) filename_config: FilenameConfig,
+    pub(crate) naming: Box<dyn NamingScheme>,
+    pub(crate) rotation: RotationPeriod,
+    pub(crate) timezone: RotationTimezone,
+    pub(crate) rotate_at: NaiveTime,
+    pub(crate) rotate_by_record_time: bool,
+    pub(crate) rotate_on_timer: bool,
+    pub(crate) new_file_on_start: bool,
     pub(crate) o_create_symlink: Option<PathBuf>,
     pub(crate) line_ending: &'static [u8],
+    pub(crate) clock: Arc<dyn Clock>,
 }
 
 impl Default for Config {
     fn default() -> Self {
+        let filename_config = FilenameConfig {
+            directory: PathBuf::from("."),
+            file_basename: String::new(),
+            suffix: "log".to_string(),
+            o_template: None,
+            o_subdirectory: None,
+            use_current_file: false,
+        };
         Self {
             print_message: false,
-            filename_config: FilenameConfig {
-                directory: PathBuf::from("."),
-                file_basename: String::new(),
-                suffix: "log".to_string(),
-            },
+            naming: Box::new(DefaultNaming::new(
+                filename_config.clone(),
+                RotationPeriod::default(),
+            )),
+            filename_config,
+            rotation: RotationPeriod::default(),
+            timezone: RotationTimezone::default(),
+            rotate_at: NaiveTime::MIN,
+            rotate_by_record_time: false,
+            rotate_on_timer: false,
+            new_file_on_start: false,
             o_buffersize: None,
+            o_max_file_size: None,
+            o_keep_files: None,
+            o_max_age: None,
+            o_max_total_size: None,
+            o_compression: None,
+            o_on_rotate: None,
+            o_postrotate_command: None,
             o_create_symlink: None,
             line_ending: UNIX_LINE_ENDING,
+            clock: Arc::new(SystemClock),
         }
     }
 }
+
+impl Config {
+    /// The path of the log file with the given index in the period that starts at `period_start`.
+    pub(crate) fn log_file_path(&self, period_start: NaiveDateTime, idx: u32) -> PathBuf {
+        self.filename_config
+            .directory
+            .join(self.naming.file_path(period_start, idx))
+    }
+
+    /// The current time as seen by the rotation, according to the clock.
+    pub(crate) fn now(&self) -> NaiveDateTime {
+        self.rotation_time(self.clock.now())
+    }
+
+    /// The wall clock time of the given instant in the configured timezone,
+    /// shifted back by the daily rotation time.
+    ///
+    /// All periods are computed from this time, so that they start at midnight.
+    pub(crate) fn rotation_time(&self, instant: DateTime<Utc>) -> NaiveDateTime {
+        let wall_time = self.timezone.naive_local(instant);
+        self.rotation.to_business_time(wall_time, self.rotate_at)
+    }
+
+    /// The wall clock time in the configured timezone of the given rotation time,
+    /// e.g. of a period start.
+    pub(crate) fn wall_time(&self, rotation_time: NaiveDateTime) -> NaiveDateTime {
+        self.rotation.to_wall_time(rotation_time, self.rotate_at)
+    }
+
+    /// How long the rotation timer has to wait until the current period ends.
+    ///
+    /// The wait is capped, so that a clock that was changed is noticed soon.
+    pub(crate) fn time_to_next_rotation(&self) -> Duration {
+        let now = self.now();
+        let period_end = self.rotation.period_end(self.rotation.period_start(now));
+        (period_end - now)
+            .to_std()
+            .unwrap_or_default()
+            .min(MAX_TIMER_WAIT)
+    }
+
+    /// Whether anything has to be done after a rotation.
+    pub(crate) const fn housekeeping_necessary(&self) -> bool {
+        self.o_compression.is_some()
+            || self.o_keep_files.is_some()
+            || self.o_max_age.is_some()
+            || self.o_max_total_size.is_some()
+            || self.o_on_rotate.is_some()
+            || self.o_postrotate_command.is_some()
+    }
+}
@@ -0,0 +1,223 @@
+use crate::{Clock, RotationTimezone};
+use chrono::{DateTime, Utc};
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc, Mutex,
+};
+
+/// The number of log records that a [`RotateLogWriter`](crate::RotateLogWriter)
+/// has dropped since it was built.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct DroppedRecords {
+    /// Records dropped because the channel of
+    /// [`WriteMode::Async`](crate::WriteMode::Async) was full.
+    pub overflow: u64,
+    /// Records dropped because the log file could not be opened or written,
+    /// or because they were logged after the writer was shut down.
+    pub io_error: u64,
+}
+
+impl DroppedRecords {
+    /// The total number of dropped records.
+    #[must_use]
+    pub const fn total(&self) -> u64 {
+        self.overflow + self.io_error
+    }
+}
+
+#[derive(Clone, Copy)]
+pub(crate) enum DropReason {
+    Overflow,
+    IoError,
+}
+
+/// Drops that have not yet been reported in the log file.
+pub(crate) struct PendingDrops {
+    count: u64,
+    first: DateTime<Utc>,
+    last: DateTime<Utc>,
+}
+
+impl PendingDrops {
+    /// The synthetic log line that reports the drops, with the times in the given timezone.
+    pub(crate) fn report(&self, timezone: RotationTimezone, line_ending: &[u8]) -> Vec<u8> {
+        let mut line = format!(
+            "[flexi_logger] {} records dropped between {} and {}",
+            self.count,
+            timezone
+                .with_offset(self.first)
+                .format("%Y-%m-%d %H:%M:%S%.6f %:z"),
+            timezone
+                .with_offset(self.last)
+                .format("%Y-%m-%d %H:%M:%S%.6f %:z"),
+        )
+        .into_bytes();
+        line.extend_from_slice(line_ending);
+        line
+    }
+}
+
+/// Counts the dropped records of a writer, shared between the logging threads
+/// and the thread that writes to the file.
+pub(crate) struct DropCounter {
+    clock: Arc<dyn Clock>,
+    overflow: AtomicU64,
+    io_error: AtomicU64,
+    pending: Mutex<Option<PendingDrops>>,
+}
+
+impl DropCounter {
+    pub(crate) fn new(clock: Arc<dyn Clock>) -> Self {
+        Self {
+            clock,
+            overflow: AtomicU64::new(0),
+            io_error: AtomicU64::new(0),
+            pending: Mutex::new(None),
+        }
+    }
+
+    pub(crate) fn record_drop(&self, reason: DropReason) {
+        match reason {
+            DropReason::Overflow => self.overflow.fetch_add(1, Ordering::Relaxed),
+            DropReason::IoError => self.io_error.fetch_add(1, Ordering::Relaxed),
+        };
+        let now = self.clock.now();
+        if let Ok(mut o_pending) = self.pending.lock() {
+            match &mut *o_pending {
+                Some(pending) => {
+                    pending.count += 1;
+                    pending.last = now;
+                }
+                None => {
+                    *o_pending = Some(PendingDrops {
+                        count: 1,
+                        first: now,
+                        last: now,
+                    });
+                }
+            }
+        }
+    }
+
+    /// Takes the drops that have not yet been reported.
+    pub(crate) fn take_pending(&self) -> Option<PendingDrops> {
+        self.pending
+            .lock()
+            .ok()
+            .and_then(|mut o_pending| o_pending.take())
+    }
+
+    /// Puts back drops whose report could not be written.
+    pub(crate) fn restore_pending(&self, restored: PendingDrops) {
+        if let Ok(mut o_pending) = self.pending.lock() {
+            *o_pending = Some(match o_pending.take() {
+                Some(pending) => PendingDrops {
+                    count: restored.count + pending.count,
+                    first: restored.first,
+                    last: pending.last,
+                },
+                None => restored,
+            });
+        }
+    }
+
+    pub(crate) fn dropped_records(&self) -> DroppedRecords {
+        DroppedRecords {
+            overflow: self.overflow.load(Ordering::Relaxed),
+            io_error: self.io_error.load(Ordering::Relaxed),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{DropCounter, DropReason, DroppedRecords};
+    use crate::{MockClock, RotationTimezone};
+    use chrono::{Duration, FixedOffset, TimeZone, Utc};
+    use std::sync::Arc;
+
+    fn new_counter() -> (DropCounter, MockClock) {
+        let clock = MockClock::new(Utc.with_ymd_and_hms(2021, 3, 28, 12, 0, 0).unwrap());
+        (DropCounter::new(Arc::new(clock.clone())), clock)
+    }
+
+    #[test]
+    fn counts_the_reasons_separately() {
+        let (counter, _) = new_counter();
+        counter.record_drop(DropReason::Overflow);
+        counter.record_drop(DropReason::IoError);
+        counter.record_drop(DropReason::Overflow);
+
+        assert_eq!(
+            counter.dropped_records(),
+            DroppedRecords {
+                overflow: 2,
+                io_error: 1
+            }
+        );
+        assert_eq!(counter.dropped_records().total(), 3);
+    }
+
+    #[test]
+    fn pending_drops_are_taken_once() {
+        let (counter, clock) = new_counter();
+        counter.record_drop(DropReason::Overflow);
+        clock.advance(Duration::seconds(5));
+        counter.record_drop(DropReason::IoError);
+
+        let pending = counter.take_pending().unwrap();
+        assert_eq!(pending.count, 2);
+        assert_eq!(pending.last - pending.first, Duration::seconds(5));
+        assert!(counter.take_pending().is_none());
+        // the totals are not affected by the report
+        assert_eq!(counter.dropped_records().total(), 2);
+    }
+
+    #[test]
+    fn restored_drops_are_merged_with_new_ones() {
+        let (counter, clock) = new_counter();
+        counter.record_drop(DropReason::Overflow);
+        let restored = counter.take_pending().unwrap();
+        clock.advance(Duration::seconds(5));
+        counter.record_drop(DropReason::IoError);
+        counter.restore_pending(restored);
+
+        let pending = counter.take_pending().unwrap();
+        assert_eq!(pending.count, 2);
+        assert_eq!(pending.last - pending.first, Duration::seconds(5));
+    }
+
+    #[test]
+    fn report_ends_with_the_line_ending() {
+        let (counter, _) = new_counter();
+        counter.record_drop(DropReason::Overflow);
+        counter.record_drop(DropReason::Overflow);
+
+        let report = counter
+            .take_pending()
+            .unwrap()
+            .report(RotationTimezone::Utc, b"\r\n");
+        let report = String::from_utf8(report).unwrap();
+        assert!(report.starts_with("[flexi_logger] 2 records dropped between "));
+        assert!(report.ends_with("\r\n"));
+    }
+
+    #[test]
+    fn report_uses_the_rotation_timezone() {
+        let (counter, clock) = new_counter();
+        counter.record_drop(DropReason::Overflow);
+        clock.advance(Duration::seconds(5));
+        counter.record_drop(DropReason::Overflow);
+
+        let offset = FixedOffset::east_opt(2 * 3600).unwrap();
+        let report = counter
+            .take_pending()
+            .unwrap()
+            .report(RotationTimezone::Fixed(offset), b"\n");
+        assert_eq!(
+            String::from_utf8(report).unwrap(),
+            "[flexi_logger] 2 records dropped between 2021-03-28 14:00:00.000000 +02:00 \
+             and 2021-03-28 14:00:05.000000 +02:00\n"
+        );
+    }
+}
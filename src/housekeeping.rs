@@ -0,0 +1,102 @@
+use crate::{
+    cleanup::remove_old_log_files, compression::compress_log_file, config::Config, RotationEvent,
+};
+use std::{
+    io::Result as IoResult,
+    panic::{self, AssertUnwindSafe},
+    path::{Path, PathBuf},
+    sync::{
+        mpsc::{Receiver, Sender},
+        Arc,
+    },
+    thread::JoinHandle,
+};
+
+enum MessageToHousekeepingThread {
+    Act {
+        o_rotated: Option<RotationEvent>,
+        active: PathBuf,
+    },
+    Die,
+}
+
+/// Handle to the background thread that does the housekeeping after a rotation,
+/// so that the logging threads are not blocked by compressing or deleting files.
+pub(crate) struct HousekeepingThreadHandle {
+    sender: Sender<MessageToHousekeepingThread>,
+    join_handle: JoinHandle<()>,
+}
+
+impl HousekeepingThreadHandle {
+    pub(crate) fn start(config: Arc<Config>) -> IoResult<Self> {
+        let (sender, receiver) = std::sync::mpsc::channel();
+        let join_handle = std::thread::Builder::new()
+            .name("flexi_logger-housekeeping".to_string())
+            .spawn(move || run(&config, &receiver))?;
+        Ok(Self {
+            sender,
+            join_handle,
+        })
+    }
+
+    /// Notifies the thread that `active` is the new log file, and that the file of `o_rotated`,
+    /// if any, was closed.
+    pub(crate) fn act(&self, o_rotated: Option<RotationEvent>, active: PathBuf) {
+        self.sender
+            .send(MessageToHousekeepingThread::Act { o_rotated, active })
+            .ok();
+    }
+
+    /// Lets the thread finish the pending work, and waits for it.
+    pub(crate) fn shutdown(self) {
+        self.sender.send(MessageToHousekeepingThread::Die).ok();
+        self.join_handle.join().ok();
+    }
+}
+
+fn run(config: &Config, receiver: &Receiver<MessageToHousekeepingThread>) {
+    while let Ok(MessageToHousekeepingThread::Act { o_rotated, active }) = receiver.recv() {
+        let mut rotated: Vec<RotationEvent> = o_rotated.into_iter().collect();
+        let mut active = active;
+        let mut die = false;
+        // catch up with the rotations that happened in the meantime,
+        // so that the cleanup does not work with an outdated active file
+        for message in receiver.try_iter() {
+            match message {
+                MessageToHousekeepingThread::Act {
+                    o_rotated,
+                    active: new_active,
+                } => {
+                    rotated.extend(o_rotated);
+                    active = new_active;
+                }
+                MessageToHousekeepingThread::Die => {
+                    die = true;
+                    break;
+                }
+            }
+        }
+        do_housekeeping(config, rotated, &active);
+        if die {
+            break;
+        }
+    }
+}
+
+fn do_housekeeping(config: &Config, rotated: Vec<RotationEvent>, active: &Path) {
+    for mut event in rotated {
+        if let Some(compression) = config.o_compression {
+            event.closed_path = compress_log_file(&event.closed_path, compression);
+        }
+        if let Some(postrotate_command) = &config.o_postrotate_command {
+            postrotate_command.run(&event.closed_path);
+        }
+        if let Some(on_rotate) = &config.o_on_rotate {
+            // a panicking callback must not stop the housekeeping
+            if panic::catch_unwind(AssertUnwindSafe(|| on_rotate(event))).is_err() {
+                eprintln!("[flexi_logger] on_rotate callback panicked");
+            }
+        }
+    }
+    remove_old_log_files(config, Some(active));
+}
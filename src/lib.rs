@@ -3,7 +3,7 @@
 //! It is just a simplified version of flexi_logger's
 //! [`FileLogWriter`](https://docs.rs/flexi_logger/0.17.1/flexi_logger/writers/struct.FileLogWriter.html).
 //! Simply rotates every day, and stores the logs in files like `foo_r2021-03-28.log`.
-//! No cleanup. No other configs.
+//! By default there is no cleanup, but it can be told to keep only the newest files.
 //!
 //! ## Example usage
 //! ```rust
@@ -26,19 +26,45 @@
 //! // ...
 //! ```
 
+use chrono::Utc;
+use config::Config;
+use drop_counter::DropCounter;
 use flexi_logger::{writers::LogWriter, DeferredNow, FormatFunction, LevelFilter, Record};
-use state::State;
+use state_handle::StateHandle;
 use std::{
     cell::RefCell,
     io::{Result as IoResult, Write},
-    sync::Mutex,
+    sync::Arc,
 };
 
 mod builder;
+mod cleanup;
+mod clock;
+mod compression;
 mod config;
+mod drop_counter;
+mod housekeeping;
+mod log_files;
+mod naming;
+pub mod policy;
+mod postrotate;
+mod rotation;
+mod rotation_event;
 mod state;
+mod state_handle;
+mod timer;
+mod write_mode;
 
 pub use builder::RotateLogWriterBuilder;
+pub use clock::{Clock, MockClock, SystemClock};
+pub use compression::Compression;
+pub use drop_counter::DroppedRecords;
+pub use log_files::LogFile;
+pub use naming::NamingScheme;
+pub use policy::RotationPolicy;
+pub use rotation::{RotationPeriod, RotationTimezone};
+pub use rotation_event::RotationEvent;
+pub use write_mode::{OverflowPolicy, WriteMode};
 
 const WINDOWS_LINE_ENDING: &[u8] = b"\r\n";
 const UNIX_LINE_ENDING: &[u8] = b"\n";
@@ -46,26 +72,31 @@ const UNIX_LINE_ENDING: &[u8] = b"\n";
 /// A simplified version of `flexi_logger`'s
 /// [`FileLogWriter`](https://docs.rs/flexi_logger/0.17.1/flexi_logger/writers/struct.FileLogWriter.html).
 ///
-/// It simply rotates every day, and stores the logs in files like `foo_r2021-03-28.log`.
-/// No cleanup. No other configs.
+/// By default, it simply rotates every day, and stores the logs in files like `foo_r2021-03-28.log`.
+/// Other rotation periods can be chosen with [`RotateLogWriterBuilder::rotate`].
 pub struct RotateLogWriter {
     format: FormatFunction,
     line_ending: &'static [u8],
-    state: Mutex<State>,
+    config: Arc<Config>,
+    state_handle: StateHandle,
+    drop_counter: Arc<DropCounter>,
     max_log_level: LevelFilter,
 }
 
 impl RotateLogWriter {
     pub(crate) fn new(
         format: FormatFunction,
-        line_ending: &'static [u8],
-        state: Mutex<State>,
+        config: Arc<Config>,
+        state_handle: StateHandle,
+        drop_counter: Arc<DropCounter>,
         max_log_level: LevelFilter,
     ) -> Self {
         Self {
             format,
-            line_ending,
-            state,
+            line_ending: config.line_ending,
+            config,
+            state_handle,
+            drop_counter,
             max_log_level,
         }
     }
@@ -75,6 +106,35 @@ impl RotateLogWriter {
     pub fn builder() -> RotateLogWriterBuilder {
         RotateLogWriterBuilder::default()
     }
+
+    /// Returns the number of log records that were dropped so far.
+    ///
+    /// Records are dropped when the channel of [`WriteMode::Async`] is full,
+    /// when the log file cannot be written, or when they are logged after the shutdown. When writing succeeds again,
+    /// a line like `N records dropped between T1 and T2` is written to the log file.
+    #[must_use]
+    pub fn dropped_records(&self) -> DroppedRecords {
+        self.drop_counter.dropped_records()
+    }
+
+    /// Lists the log files of this writer that exist in the log directory,
+    /// including the currently active one, oldest first.
+    ///
+    /// Only the files whose path matches the naming of this writer are listed,
+    /// i.e. its basename, discriminant and suffix, or its filename template.
+    /// With [`use_current_file`](RotateLogWriterBuilder::use_current_file),
+    /// the current file is not listed, since it has no date yet.
+    ///
+    /// # Errors
+    ///
+    /// If the log directory cannot be read.
+    pub fn log_files(&self) -> IoResult<Vec<LogFile>> {
+        let mut log_files = log_files::list_log_files(&self.config)?;
+        for log_file in &mut log_files {
+            log_file.period_start = self.config.wall_time(log_file.period_start);
+        }
+        Ok(log_files)
+    }
 }
 
 impl LogWriter for RotateLogWriter {
@@ -87,14 +147,13 @@ impl LogWriter for RotateLogWriter {
             Ok(mut buffer) => {
                 (self.format)(&mut *buffer, now, record).unwrap_or_else(|e| write_err(ERR_1, &e));
 
-                let mut state = self.state.lock().unwrap();
-
                 buffer
                     .write_all(self.line_ending)
                     .unwrap_or_else(|e| write_err(ERR_2, &e));
 
-                state
-                    .write_buffer(&*buffer)
+                let record_time = now.now().with_timezone(&Utc);
+                self.state_handle
+                    .write_buffer(&buffer, record_time)
                     .unwrap_or_else(|e| write_err(ERR_2, &e));
                 buffer.clear();
             }
@@ -106,14 +165,13 @@ impl LogWriter for RotateLogWriter {
                 let mut tmp_buf = Vec::<u8>::with_capacity(200);
                 (self.format)(&mut tmp_buf, now, record).unwrap_or_else(|e| write_err(ERR_1, &e));
 
-                let mut state = self.state.lock().unwrap();
-
                 tmp_buf
                     .write_all(self.line_ending)
                     .unwrap_or_else(|e| write_err(ERR_2, &e));
 
-                state
-                    .write_buffer(&tmp_buf)
+                let record_time = now.now().with_timezone(&Utc);
+                self.state_handle
+                    .write_buffer(&tmp_buf, record_time)
                     .unwrap_or_else(|e| write_err(ERR_2, &e));
             }
         });
@@ -122,11 +180,7 @@ impl LogWriter for RotateLogWriter {
 
     #[inline]
     fn flush(&self) -> IoResult<()> {
-        if let Ok(mut state) = self.state.lock() {
-            state.flush()
-        } else {
-            Ok(())
-        }
+        self.state_handle.flush()
     }
 
     #[inline]
@@ -138,11 +192,21 @@ impl LogWriter for RotateLogWriter {
     fn format(&mut self, format: FormatFunction) {
         self.format = format;
     }
+
+    fn shutdown(&self) {
+        self.state_handle.shutdown();
+    }
+}
+
+impl Drop for RotateLogWriter {
+    fn drop(&mut self) {
+        self.shutdown();
+    }
 }
 
 const ERR_1: &str = "FileLogWriter: formatting failed with ";
-const ERR_2: &str = "FileLogWriter: writing failed with ";
+pub(crate) const ERR_2: &str = "FileLogWriter: writing failed with ";
 
-fn write_err(msg: &str, err: &std::io::Error) {
+pub(crate) fn write_err(msg: &str, err: &std::io::Error) {
     eprintln!("[flexi_logger] {} with {}", msg, err);
 }
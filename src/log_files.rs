@@ -0,0 +1,78 @@
+use crate::{compression::strip_archive_extension, config::Config};
+use chrono::NaiveDateTime;
+use std::{
+    io::Result as IoResult,
+    path::{Path, PathBuf},
+};
+
+/// A log file of a [`RotateLogWriter`](crate::RotateLogWriter) that was found in the log directory.
+///
+/// See [`RotateLogWriter::log_files`](crate::RotateLogWriter::log_files).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LogFile {
+    /// The path of the file.
+    pub path: PathBuf,
+    /// The start of the period the file belongs to, in the rotation timezone.
+    ///
+    /// With [`rotate_at`](crate::RotateLogWriterBuilder::rotate_at), this is the time of the
+    /// rotation, e.g. 04:00 on the date in the file name, not midnight.
+    pub period_start: NaiveDateTime,
+    /// The sequence number of the file within its period, starting with 0.
+    pub sequence: u32,
+    /// The size of the file in bytes.
+    pub size: u64,
+    /// Whether the file is compressed.
+    pub compressed: bool,
+}
+
+/// Lists the log files in the log directory that belong to this writer, oldest first.
+///
+/// Files whose path is not recognized by the naming scheme of this writer are ignored.
+/// With date-partitioned subdirectories, the subdirectories are searched as well.
+pub(crate) fn list_log_files(config: &Config) -> IoResult<Vec<LogFile>> {
+    let depth = config.naming.subdirectory_depth();
+    let mut log_files = Vec::new();
+    collect_log_files(
+        config,
+        &config.filename_config.directory,
+        &PathBuf::new(),
+        depth,
+        &mut log_files,
+    )?;
+    log_files.sort_by_key(|log_file| (log_file.period_start, log_file.sequence));
+    Ok(log_files)
+}
+
+fn collect_log_files(
+    config: &Config,
+    dir: &Path,
+    relative_dir: &Path,
+    depth: usize,
+    log_files: &mut Vec<LogFile>,
+) -> IoResult<()> {
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        let metadata = entry.metadata()?;
+        let file_name = entry.file_name();
+        if metadata.is_dir() && depth > 0 {
+            let relative_path = relative_dir.join(file_name);
+            collect_log_files(config, &entry.path(), &relative_path, depth - 1, log_files)?;
+        } else if metadata.is_file() && depth == 0 {
+            let o_file_name = file_name.to_str();
+            let o_stripped = o_file_name.and_then(strip_archive_extension);
+            let o_parsed = o_stripped
+                .or(o_file_name)
+                .and_then(|name| config.naming.parse_path(&relative_dir.join(name)));
+            if let Some((period_start, sequence)) = o_parsed {
+                log_files.push(LogFile {
+                    path: entry.path(),
+                    period_start,
+                    sequence,
+                    size: metadata.len(),
+                    compressed: o_stripped.is_some(),
+                });
+            }
+        }
+    }
+    Ok(())
+}
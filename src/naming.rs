@@ -0,0 +1,520 @@
+use crate::{config::FilenameConfig, rotation, RotationPeriod};
+use chrono::{
+    format::{parse, Item, Parsed, StrftimeItems},
+    NaiveDate, NaiveDateTime, NaiveTime,
+};
+use regex::Regex;
+use std::path::{Path, PathBuf};
+
+/// A part of a [`FilenameTemplate`].
+#[derive(Clone)]
+enum Part {
+    Literal(String),
+    Date(String),
+    Seq,
+    Pid,
+}
+
+/// A file name template like `{basename}-{date:%Y-%m-%d}.log`, with the placeholders
+/// that do not change during the lifetime of the writer already filled in.
+#[derive(Clone)]
+pub(crate) struct FilenameTemplate {
+    parts: Vec<Part>,
+    // matches the file names produced by the template, with a capture group for each part
+    // that varies
+    regex: Regex,
+}
+
+impl FilenameTemplate {
+    /// Parses a template, and fills in the basename, the discriminant and the hostname.
+    ///
+    /// A `{date}` without a format uses the default date format of the rotation period.
+    pub(crate) fn parse(
+        template: &str,
+        basename: &str,
+        o_discriminant: Option<&str>,
+        rotation: RotationPeriod,
+    ) -> Result<Self, String> {
+        let mut parts = Vec::new();
+        let mut rest = template;
+        while let Some(open) = rest.find('{') {
+            if open > 0 {
+                parts.push(Part::Literal(rest[..open].to_string()));
+            }
+            let close = rest[open..]
+                .find('}')
+                .ok_or_else(|| format!("unclosed placeholder in \"{}\"", template))?
+                + open;
+            let placeholder = &rest[open + 1..close];
+            parts.push(match placeholder.split_once(':') {
+                None if placeholder == "basename" => Part::Literal(basename.to_string()),
+                None if placeholder == "discriminant" => Part::Literal(
+                    o_discriminant
+                        .ok_or("{discriminant} is used, but no discriminant is set")?
+                        .to_string(),
+                ),
+                None if placeholder == "date" => Part::Date(rotation.date_format().to_string()),
+                Some(("date", format)) => Part::Date(format.to_string()),
+                None if placeholder == "seq" => Part::Seq,
+                None if placeholder == "pid" => Part::Pid,
+                None if placeholder == "hostname" => Part::Literal(
+                    hostname::get()
+                        .map_err(|e| format!("cannot get the hostname: {}", e))?
+                        .to_string_lossy()
+                        .into_owned(),
+                ),
+                _ => return Err(format!("unknown placeholder {{{}}}", placeholder)),
+            });
+            rest = &rest[close + 1..];
+        }
+        if !rest.is_empty() {
+            parts.push(Part::Literal(rest.to_string()));
+        }
+
+        if parts
+            .iter()
+            .filter(|part| matches!(part, Part::Seq))
+            .count()
+            > 1
+        {
+            return Err("{seq} can be used only once".to_string());
+        }
+
+        let regex = Regex::new(&build_regex(&parts)?).map_err(|e| e.to_string())?;
+        Ok(Self { parts, regex })
+    }
+
+    /// Whether the template contains a date placeholder.
+    pub(crate) fn has_date(&self) -> bool {
+        self.parts.iter().any(|part| matches!(part, Part::Date(_)))
+    }
+
+    /// Whether the template contains the `{seq}` placeholder.
+    pub(crate) fn has_seq(&self) -> bool {
+        self.parts.iter().any(|part| matches!(part, Part::Seq))
+    }
+
+    fn render(&self, period_start: NaiveDateTime, idx: u32) -> String {
+        let mut file_name = String::new();
+        for part in &self.parts {
+            match part {
+                Part::Literal(literal) => file_name.push_str(literal),
+                Part::Date(format) => file_name.push_str(&period_start.format(format).to_string()),
+                Part::Seq => file_name.push_str(&idx.to_string()),
+                Part::Pid => file_name.push_str(&std::process::id().to_string()),
+            }
+        }
+        file_name
+    }
+
+    /// Parses a file name produced by the template, adding the date fields to `parsed`,
+    /// and returns the sequence number.
+    fn parse_file_name(&self, file_name: &str, parsed: &mut Parsed) -> Option<u32> {
+        let captures = self.regex.captures(file_name)?;
+        let mut idx = 0;
+        for (i, part) in self.parts.iter().enumerate() {
+            match part {
+                Part::Date(format) => {
+                    let date = captures.name(&format!("p{}", i))?.as_str();
+                    parse(parsed, date, StrftimeItems::new(format)).ok()?;
+                }
+                Part::Seq => idx = captures.name(&format!("p{}", i))?.as_str().parse().ok()?,
+                Part::Literal(_) | Part::Pid => {}
+            }
+        }
+        Some(idx)
+    }
+}
+
+/// Checks that a subdirectory format is a valid `strftime` format.
+pub(crate) fn validate_subdirectory(format: &str) -> Result<(), String> {
+    if StrftimeItems::new(format).any(|item| matches!(item, Item::Error)) {
+        Err(format!("invalid date format \"{}\"", format))
+    } else {
+        Ok(())
+    }
+}
+
+fn build_regex(parts: &[Part]) -> Result<String, String> {
+    let mut regex = String::from("^");
+    for (i, part) in parts.iter().enumerate() {
+        match part {
+            Part::Literal(literal) => regex.push_str(&regex::escape(literal)),
+            Part::Date(format) => {
+                regex.push_str(&format!("(?P<p{}>", i));
+                for item in StrftimeItems::new(format) {
+                    match item {
+                        Item::Literal(literal) => regex.push_str(&regex::escape(literal)),
+                        Item::OwnedLiteral(literal) => regex.push_str(&regex::escape(&literal)),
+                        Item::Space(_) | Item::OwnedSpace(_) => regex.push_str(r"\s*"),
+                        Item::Numeric(_, _) => regex.push_str(r" *\d+"),
+                        Item::Fixed(_) => regex.push_str(r"[^/\\]+?"),
+                        Item::Error => return Err(format!("invalid date format \"{}\"", format)),
+                    }
+                }
+                regex.push(')');
+            }
+            Part::Seq => regex.push_str(&format!(r"(?P<p{}>\d+)", i)),
+            Part::Pid => regex.push_str(r"\d+"),
+        }
+    }
+    regex.push('$');
+    Ok(regex)
+}
+
+/// Determines the paths of the log files, relative to the log directory.
+///
+/// By default, the log files are named like `foo_r2021-03-28.log`, or as specified with
+/// [`filename_template`](crate::RotateLogWriterBuilder::filename_template) and
+/// [`subdirectory`](crate::RotateLogWriterBuilder::subdirectory). A custom naming scheme
+/// can be set with [`naming_scheme`](crate::RotateLogWriterBuilder::naming_scheme):
+///
+/// ```rust
+/// use chrono::NaiveDateTime;
+/// use flexi_logger_rotate_writer::{NamingScheme, RotateLogWriter};
+/// use std::path::{Path, PathBuf};
+///
+/// struct WithDeployId(String);
+///
+/// impl NamingScheme for WithDeployId {
+///     fn file_path(&self, period_start: NaiveDateTime, sequence: u32) -> PathBuf {
+///         let date = period_start.format("%Y-%m-%d");
+///         PathBuf::from(format!("app_{}_{}_{}.log", self.0, date, sequence))
+///     }
+///
+///     fn parse_path(&self, path: &Path) -> Option<(NaiveDateTime, u32)> {
+///         let rest = path
+///             .to_str()?
+///             .strip_prefix(&format!("app_{}_", self.0))?
+///             .strip_suffix(".log")?;
+///         let (date, sequence) = rest.rsplit_once('_')?;
+///         let period_start = chrono::NaiveDate::parse_from_str(date, "%Y-%m-%d")
+///             .ok()?
+///             .and_hms_opt(0, 0, 0)?;
+///         Some((period_start, sequence.parse().ok()?))
+///     }
+/// }
+///
+/// let builder = RotateLogWriter::builder().naming_scheme(WithDeployId("d42".to_string()));
+/// ```
+pub trait NamingScheme: Send + Sync {
+    /// The path of the log file with the given sequence number in the period that starts at
+    /// `period_start`, relative to the log directory.
+    ///
+    /// `period_start` is in the rotation timezone. Different periods and sequence numbers
+    /// must give different paths. If the path does not depend on `sequence`, a rotation within
+    /// a period, e.g. by [`max_file_size`](crate::RotateLogWriterBuilder::max_file_size),
+    /// is skipped and the current file is continued.
+    /// The extension of compressed files is appended to the path.
+    fn file_path(&self, period_start: NaiveDateTime, sequence: u32) -> PathBuf;
+
+    /// Parses a path produced by [`file_path`](Self::file_path) back into the start of the
+    /// period and the sequence number, or returns `None` if the path does not belong to
+    /// this naming scheme.
+    ///
+    /// The path is relative to the log directory, and the extension of compressed files
+    /// is already removed. This is used for the retention and
+    /// [`log_files`](crate::RotateLogWriter::log_files).
+    fn parse_path(&self, path: &Path) -> Option<(NaiveDateTime, u32)>;
+
+    /// The number of subdirectories in the paths, which are searched for log files.
+    fn subdirectory_depth(&self) -> usize {
+        0
+    }
+}
+
+/// The naming scheme that is used if no custom one is specified: the basename,
+/// the date infix and the suffix, or a template, optionally in date-partitioned subdirectories.
+pub(crate) struct DefaultNaming {
+    filename_config: FilenameConfig,
+    rotation: RotationPeriod,
+}
+
+impl DefaultNaming {
+    pub(crate) fn new(filename_config: FilenameConfig, rotation: RotationPeriod) -> Self {
+        Self {
+            filename_config,
+            rotation,
+        }
+    }
+}
+
+impl NamingScheme for DefaultNaming {
+    fn file_path(&self, period_start: NaiveDateTime, idx: u32) -> PathBuf {
+        let config = &self.filename_config;
+        let s_filename = if let Some(template) = &config.o_template {
+            template.render(period_start, idx)
+        } else {
+            let date_infix = period_start.format(self.rotation.date_format()).to_string();
+            if idx == 0 {
+                format!("{}_r{}.{}", config.file_basename, date_infix, config.suffix)
+            } else {
+                format!(
+                    "{}_r{}.{}.{}",
+                    config.file_basename, date_infix, idx, config.suffix
+                )
+            }
+        };
+        let mut p_path = PathBuf::new();
+        if let Some(subdirectory) = &config.o_subdirectory {
+            p_path.push(period_start.format(subdirectory).to_string());
+        }
+        p_path.push(s_filename);
+        p_path
+    }
+
+    // the date fields can be spread over the subdirectories and the file name
+    fn parse_path(&self, relative_path: &Path) -> Option<(NaiveDateTime, u32)> {
+        let config = &self.filename_config;
+        let file_name = relative_path.file_name()?.to_str()?;
+        let mut parsed = Parsed::new();
+
+        let dir_components = relative_path
+            .parent()?
+            .components()
+            .map(|component| component.as_os_str().to_str())
+            .collect::<Option<Vec<&str>>>()?;
+        match &config.o_subdirectory {
+            Some(subdirectory) => {
+                // the format uses '/' as separator on all platforms
+                let subdir = dir_components.join("/");
+                parse(&mut parsed, &subdir, StrftimeItems::new(subdirectory)).ok()?;
+            }
+            None if !dir_components.is_empty() => return None,
+            None => {}
+        }
+
+        let idx = if let Some(template) = &config.o_template {
+            template.parse_file_name(file_name, &mut parsed)?
+        } else {
+            let infix = file_name
+                .strip_prefix(&config.file_basename)?
+                .strip_prefix("_r")?
+                .strip_suffix(&config.suffix)?
+                .strip_suffix('.')?;
+            let (date_infix, idx) = match infix.split_once('.') {
+                Some((date_infix, idx)) => (date_infix, idx.parse().ok()?),
+                None => (infix, 0),
+            };
+            parse(
+                &mut parsed,
+                date_infix,
+                StrftimeItems::new(self.rotation.date_format()),
+            )
+            .ok()?;
+            idx
+        };
+        let period_start = rotation::to_naive_datetime(&mut parsed)?;
+        Some((period_start, idx))
+    }
+
+    // counted in a rendered path, since specifiers like %D contain separators as well
+    fn subdirectory_depth(&self) -> usize {
+        self.filename_config
+            .o_subdirectory
+            .as_ref()
+            .map_or(0, |subdirectory| {
+                let sample = NaiveDate::from_ymd_opt(2021, 3, 28)
+                    .unwrap_or_default()
+                    .and_time(NaiveTime::MIN);
+                Path::new(&sample.format(subdirectory).to_string())
+                    .components()
+                    .count()
+            })
+    }
+}
+
+/// The path of the current log file, if the log is always written to the same file.
+pub(crate) fn get_current_filepath(config: &FilenameConfig) -> PathBuf {
+    let mut p_path = config.directory.to_path_buf();
+    p_path.push(format!("{}.{}", config.file_basename, config.suffix));
+    p_path
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{DefaultNaming, FilenameTemplate, NamingScheme};
+    use crate::{config::FilenameConfig, RotationPeriod};
+    use chrono::{NaiveDate, NaiveDateTime};
+    use std::path::{Path, PathBuf};
+
+    fn naming(
+        o_template: Option<&str>,
+        o_subdirectory: Option<&str>,
+        rotation: RotationPeriod,
+    ) -> DefaultNaming {
+        let filename_config = FilenameConfig {
+            directory: PathBuf::from("log"),
+            file_basename: "foo".to_string(),
+            suffix: "log".to_string(),
+            o_template: o_template
+                .map(|template| FilenameTemplate::parse(template, "foo", None, rotation).unwrap()),
+            o_subdirectory: o_subdirectory.map(str::to_string),
+            use_current_file: false,
+        };
+        DefaultNaming::new(filename_config, rotation)
+    }
+
+    fn datetime(year: i32, month: u32, day: u32, hour: u32) -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(year, month, day)
+            .unwrap()
+            .and_hms_opt(hour, 0, 0)
+            .unwrap()
+    }
+
+    fn assert_round_trip(naming: &DefaultNaming, period_start: NaiveDateTime, idx: u32) {
+        let path = naming.file_path(period_start, idx);
+        assert_eq!(
+            naming.parse_path(&path),
+            Some((period_start, idx)),
+            "{:?}",
+            path
+        );
+    }
+
+    #[test]
+    fn default_names() {
+        let naming = naming(None, None, RotationPeriod::Daily);
+        let period_start = datetime(2021, 3, 28, 0);
+        assert_eq!(
+            naming.file_path(period_start, 0),
+            Path::new("foo_r2021-03-28.log")
+        );
+        assert_eq!(
+            naming.file_path(period_start, 2),
+            Path::new("foo_r2021-03-28.2.log")
+        );
+        assert_round_trip(&naming, period_start, 0);
+        assert_round_trip(&naming, period_start, 2);
+        assert_eq!(
+            naming.parse_path(Path::new("foo_bar_r2021-03-28.log")),
+            None
+        );
+        assert_eq!(naming.parse_path(Path::new("foo_r2021-03-28.txt")), None);
+    }
+
+    #[test]
+    fn compact_date() {
+        let naming = naming(
+            Some("{basename}-{date:%Y%m%d}.log"),
+            None,
+            RotationPeriod::Daily,
+        );
+        let period_start = datetime(2021, 3, 28, 0);
+        assert_eq!(
+            naming.file_path(period_start, 0),
+            Path::new("foo-20210328.log")
+        );
+        assert_round_trip(&naming, period_start, 0);
+        assert_eq!(naming.parse_path(Path::new("foo-latest.log")), None);
+    }
+
+    #[test]
+    fn iso_week() {
+        let naming = naming(Some("{basename}.{date}.log"), None, RotationPeriod::Weekly);
+        // the monday of the first ISO week of 2021
+        let period_start = datetime(2021, 1, 4, 0);
+        assert_eq!(
+            naming.file_path(period_start, 0),
+            Path::new("foo.2021-W01.log")
+        );
+        assert_round_trip(&naming, period_start, 0);
+        // the ISO year differs from the calendar year
+        assert_round_trip(&naming, datetime(2020, 12, 28, 0), 0);
+    }
+
+    #[test]
+    fn seq() {
+        let naming = naming(
+            Some("{basename}_{date:%Y-%m-%d_%H}_{seq}.log"),
+            None,
+            RotationPeriod::Hourly,
+        );
+        let period_start = datetime(2021, 3, 28, 13);
+        assert_eq!(
+            naming.file_path(period_start, 12),
+            Path::new("foo_2021-03-28_13_12.log")
+        );
+        assert_round_trip(&naming, period_start, 0);
+        assert_round_trip(&naming, period_start, 12);
+        assert_eq!(
+            naming.parse_path(Path::new("foo_2021-03-28_13_x.log")),
+            None
+        );
+    }
+
+    #[test]
+    fn pid() {
+        let naming = naming(
+            Some("{basename}-{pid}-{date}-{seq}.log"),
+            None,
+            RotationPeriod::Daily,
+        );
+        let period_start = datetime(2021, 3, 28, 0);
+        assert_eq!(
+            naming.file_path(period_start, 1),
+            PathBuf::from(format!("foo-{}-2021-03-28-1.log", std::process::id()))
+        );
+        assert_round_trip(&naming, period_start, 1);
+        // files of other processes are recognized as well
+        assert_eq!(
+            naming.parse_path(Path::new("foo-1-2021-03-28-3.log")),
+            Some((period_start, 3))
+        );
+    }
+
+    #[test]
+    fn date_split_across_subdirectory_and_file_name() {
+        let naming = naming(
+            Some("{basename}_r{date:%d}.log"),
+            Some("%Y-%m"),
+            RotationPeriod::Daily,
+        );
+        let period_start = datetime(2021, 3, 28, 0);
+        assert_eq!(
+            naming.file_path(period_start, 0),
+            Path::new("2021-03").join("foo_r28.log")
+        );
+        assert_eq!(naming.subdirectory_depth(), 1);
+        assert_round_trip(&naming, period_start, 0);
+        assert_eq!(naming.parse_path(Path::new("foo_r28.log")), None);
+    }
+
+    #[test]
+    fn nested_subdirectories() {
+        let naming = naming(None, Some("%Y/%m/%d"), RotationPeriod::Daily);
+        let period_start = datetime(2021, 3, 28, 0);
+        assert_eq!(naming.subdirectory_depth(), 3);
+        assert_round_trip(&naming, period_start, 0);
+        // the date in the directories must match the one in the file name
+        assert_eq!(
+            naming.parse_path(Path::new("2021/03/27/foo_r2021-03-28.log")),
+            None
+        );
+    }
+
+    #[test]
+    fn separators_within_specifiers() {
+        // %D is %m/%d/%y
+        let naming = naming(None, Some("%D"), RotationPeriod::Daily);
+        let period_start = datetime(2021, 3, 28, 0);
+        assert_eq!(
+            naming.file_path(period_start, 0),
+            Path::new("03/28/21").join("foo_r2021-03-28.log")
+        );
+        assert_eq!(naming.subdirectory_depth(), 3);
+        assert_round_trip(&naming, period_start, 0);
+    }
+
+    #[test]
+    fn rejected_templates() {
+        let parse =
+            |template| FilenameTemplate::parse(template, "foo", None, RotationPeriod::Daily);
+        assert!(parse("{basename}-{date").is_err());
+        assert!(parse("{basename}-{date}-{level}.log").is_err());
+        assert!(parse("{date}-{seq}-{seq}.log").is_err());
+        assert!(parse("{discriminant}-{date}.log").is_err());
+        assert!(parse("{date:%Q}.log").is_err());
+        assert!(parse("{basename}-{date}-{seq}.log").is_ok());
+    }
+}
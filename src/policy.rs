@@ -0,0 +1,342 @@
+//! Rotation policies, which decide when the current log file is closed and a new one is opened.
+//!
+//! By default, a [`RotateLogWriter`](crate::RotateLogWriter) rotates at the end of each
+//! [`RotationPeriod`], and when the [`max_file_size`](crate::RotateLogWriterBuilder::max_file_size)
+//! is reached. A custom policy can be set with
+//! [`rotation_policy`](crate::RotateLogWriterBuilder::rotation_policy):
+//!
+//! ```rust
+//! use flexi_logger_rotate_writer::{policy, RotateLogWriter};
+//!
+//! // rotate every day, but only if the file has at least 1000 lines,
+//! // or when it reaches 10 MB
+//! let builder = RotateLogWriter::builder().rotation_policy(policy::any_of(vec![
+//!     policy::all_of(vec![policy::daily(), policy::max_lines(1000)]),
+//!     policy::max_size(10_000_000),
+//! ]));
+//! ```
+//!
+//! The [`RotationPeriod`] of the writer still determines the date in the file names,
+//! which is the start of the period in which the file was opened. Since a policy can rotate
+//! more than once per period, a [`filename_template`](crate::RotateLogWriterBuilder::filename_template)
+//! needs to contain `{seq}`.
+
+use crate::RotationPeriod;
+use chrono::NaiveDateTime;
+
+/// Decides when the current log file is rotated.
+///
+/// The writer informs the policy about what happens to the current log file,
+/// and asks it with [`rotation_necessary`](Self::rotation_necessary) before each write,
+/// and on each tick of the timer if
+/// [`rotate_on_timer`](crate::RotateLogWriterBuilder::rotate_on_timer) is used.
+///
+/// All times are in the rotation timezone, and shifted by
+/// [`rotate_at`](crate::RotateLogWriterBuilder::rotate_at).
+pub trait RotationPolicy: Send {
+    /// A log file was opened.
+    ///
+    /// `period_start` is the start of the period that the file belongs to, and
+    /// `size` is the size of the file, which is not 0 if an existing file is continued.
+    fn file_opened(&mut self, _period_start: NaiveDateTime, _size: u64) {}
+
+    /// Bytes were written to the current log file.
+    fn bytes_written(&mut self, _bytes: u64) {}
+
+    /// A log record was written to the current log file.
+    fn record_written(&mut self) {}
+
+    /// Time passed. This is called before [`rotation_necessary`](Self::rotation_necessary),
+    /// with the current time, or with the time of the record if
+    /// [`rotate_by_record_time`](crate::RotateLogWriterBuilder::rotate_by_record_time) is used.
+    fn time_passed(&mut self, _now: NaiveDateTime) {}
+
+    /// Whether the current log file should be rotated now.
+    fn rotation_necessary(&self) -> bool;
+}
+
+struct Periodic {
+    period: RotationPeriod,
+    o_file_period_start: Option<NaiveDateTime>,
+    o_now: Option<NaiveDateTime>,
+}
+
+impl RotationPolicy for Periodic {
+    fn file_opened(&mut self, period_start: NaiveDateTime, _size: u64) {
+        self.o_file_period_start = Some(self.period.period_start(period_start));
+    }
+
+    fn time_passed(&mut self, now: NaiveDateTime) {
+        self.o_now = Some(now);
+    }
+
+    fn rotation_necessary(&self) -> bool {
+        match (self.o_file_period_start, self.o_now) {
+            // never go back to an older period
+            (Some(file_period_start), Some(now)) => {
+                self.period.period_start(now) > file_period_start
+            }
+            _ => false,
+        }
+    }
+}
+
+struct MaxSize {
+    max_bytes: u64,
+    bytes: u64,
+}
+
+impl RotationPolicy for MaxSize {
+    fn file_opened(&mut self, _period_start: NaiveDateTime, size: u64) {
+        self.bytes = size;
+    }
+
+    fn bytes_written(&mut self, bytes: u64) {
+        self.bytes += bytes;
+    }
+
+    fn rotation_necessary(&self) -> bool {
+        self.bytes >= self.max_bytes
+    }
+}
+
+struct MaxLines {
+    max_lines: u64,
+    lines: u64,
+}
+
+impl RotationPolicy for MaxLines {
+    fn file_opened(&mut self, _period_start: NaiveDateTime, _size: u64) {
+        self.lines = 0;
+    }
+
+    fn record_written(&mut self) {
+        self.lines += 1;
+    }
+
+    fn rotation_necessary(&self) -> bool {
+        self.lines >= self.max_lines
+    }
+}
+
+struct Combination {
+    policies: Vec<Box<dyn RotationPolicy>>,
+    require_all: bool,
+}
+
+impl RotationPolicy for Combination {
+    fn file_opened(&mut self, period_start: NaiveDateTime, size: u64) {
+        for policy in &mut self.policies {
+            policy.file_opened(period_start, size);
+        }
+    }
+
+    fn bytes_written(&mut self, bytes: u64) {
+        for policy in &mut self.policies {
+            policy.bytes_written(bytes);
+        }
+    }
+
+    fn record_written(&mut self) {
+        for policy in &mut self.policies {
+            policy.record_written();
+        }
+    }
+
+    fn time_passed(&mut self, now: NaiveDateTime) {
+        for policy in &mut self.policies {
+            policy.time_passed(now);
+        }
+    }
+
+    fn rotation_necessary(&self) -> bool {
+        if self.require_all {
+            self.policies
+                .iter()
+                .all(|policy| policy.rotation_necessary())
+        } else {
+            self.policies
+                .iter()
+                .any(|policy| policy.rotation_necessary())
+        }
+    }
+}
+
+/// Rotates when a new period begins, e.g. at midnight for [`RotationPeriod::Daily`].
+///
+/// If the clock is set back, the rotation waits until time catches up.
+#[must_use]
+pub fn periodic(period: RotationPeriod) -> Box<dyn RotationPolicy> {
+    Box::new(Periodic {
+        period,
+        o_file_period_start: None,
+        o_now: None,
+    })
+}
+
+/// Rotates when a new day begins.
+#[must_use]
+pub fn daily() -> Box<dyn RotationPolicy> {
+    periodic(RotationPeriod::Daily)
+}
+
+/// Rotates when the current log file has reached the specified size in bytes.
+#[must_use]
+pub fn max_size(max_bytes: u64) -> Box<dyn RotationPolicy> {
+    Box::new(MaxSize {
+        max_bytes,
+        bytes: 0,
+    })
+}
+
+/// Rotates after the specified number of log records was written to the current log file.
+///
+/// Only the records written by this writer are counted, not those in a continued file.
+#[must_use]
+pub fn max_lines(max_lines: u64) -> Box<dyn RotationPolicy> {
+    Box::new(MaxLines {
+        max_lines,
+        lines: 0,
+    })
+}
+
+/// Rotates if any of the policies wants to rotate. Never rotates if `policies` is empty.
+#[must_use]
+pub fn any_of(policies: Vec<Box<dyn RotationPolicy>>) -> Box<dyn RotationPolicy> {
+    Box::new(Combination {
+        policies,
+        require_all: false,
+    })
+}
+
+/// Rotates if all of the policies want to rotate. Always rotates if `policies` is empty.
+#[must_use]
+pub fn all_of(policies: Vec<Box<dyn RotationPolicy>>) -> Box<dyn RotationPolicy> {
+    Box::new(Combination {
+        policies,
+        require_all: true,
+    })
+}
+
+/// The policy that is used if none is specified: rotate when a new period begins,
+/// or when the maximum file size is reached.
+pub(crate) fn default_policy(
+    period: RotationPeriod,
+    o_max_file_size: Option<u64>,
+) -> Box<dyn RotationPolicy> {
+    let mut policies = vec![periodic(period)];
+    if let Some(max_bytes) = o_max_file_size {
+        policies.push(max_size(max_bytes));
+    }
+    any_of(policies)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{all_of, any_of, daily, max_lines, max_size, RotationPolicy};
+    use chrono::{NaiveDate, NaiveDateTime};
+
+    fn datetime(day: u32, hour: u32) -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(2021, 3, day)
+            .unwrap()
+            .and_hms_opt(hour, 0, 0)
+            .unwrap()
+    }
+
+    fn write_record(policy: &mut dyn RotationPolicy, bytes: u64) {
+        policy.bytes_written(bytes);
+        policy.record_written();
+    }
+
+    #[test]
+    fn max_size_counts_the_existing_size() {
+        let mut policy = max_size(100);
+        policy.file_opened(datetime(28, 0), 90);
+        assert!(!policy.rotation_necessary());
+        write_record(policy.as_mut(), 10);
+        assert!(policy.rotation_necessary());
+        policy.file_opened(datetime(28, 0), 0);
+        assert!(!policy.rotation_necessary());
+    }
+
+    #[test]
+    fn max_lines_counts_the_written_records() {
+        let mut policy = max_lines(2);
+        // the lines of a continued file are not counted
+        policy.file_opened(datetime(28, 0), 1000);
+        write_record(policy.as_mut(), 10);
+        assert!(!policy.rotation_necessary());
+        // bytes alone are not a record
+        policy.bytes_written(10);
+        assert!(!policy.rotation_necessary());
+        write_record(policy.as_mut(), 10);
+        assert!(policy.rotation_necessary());
+        policy.file_opened(datetime(28, 0), 0);
+        assert!(!policy.rotation_necessary());
+    }
+
+    #[test]
+    fn daily_rotates_in_the_next_day_only() {
+        let mut policy = daily();
+        policy.file_opened(datetime(28, 0), 0);
+        assert!(!policy.rotation_necessary());
+        policy.time_passed(datetime(28, 23));
+        assert!(!policy.rotation_necessary());
+        policy.time_passed(datetime(29, 0));
+        assert!(policy.rotation_necessary());
+        // never back to an older period
+        policy.time_passed(datetime(27, 12));
+        assert!(!policy.rotation_necessary());
+    }
+
+    #[test]
+    fn any_of_rotates_if_one_policy_does() {
+        let mut policy = any_of(vec![daily(), max_lines(2)]);
+        policy.file_opened(datetime(28, 0), 0);
+        policy.time_passed(datetime(28, 12));
+        write_record(policy.as_mut(), 10);
+        assert!(!policy.rotation_necessary());
+        write_record(policy.as_mut(), 10);
+        assert!(policy.rotation_necessary());
+        policy.file_opened(datetime(28, 0), 0);
+        assert!(!policy.rotation_necessary());
+        policy.time_passed(datetime(29, 0));
+        assert!(policy.rotation_necessary());
+    }
+
+    #[test]
+    fn all_of_rotates_if_all_policies_do() {
+        let mut policy = all_of(vec![daily(), max_size(100)]);
+        policy.file_opened(datetime(28, 0), 0);
+        policy.time_passed(datetime(29, 0));
+        assert!(!policy.rotation_necessary());
+        write_record(policy.as_mut(), 100);
+        assert!(policy.rotation_necessary());
+        policy.file_opened(datetime(29, 0), 100);
+        assert!(!policy.rotation_necessary());
+    }
+
+    #[test]
+    fn empty_combinations() {
+        let mut never = any_of(Vec::new());
+        let mut always = all_of(Vec::new());
+        for policy in [&mut never, &mut always] {
+            policy.file_opened(datetime(28, 0), 1000);
+            write_record(policy.as_mut(), 1000);
+            policy.time_passed(datetime(29, 0));
+        }
+        assert!(!never.rotation_necessary());
+        assert!(always.rotation_necessary());
+    }
+
+    #[test]
+    fn nested_combinations() {
+        let mut policy = any_of(vec![all_of(vec![daily(), max_lines(2)]), max_size(100)]);
+        policy.file_opened(datetime(28, 0), 0);
+        policy.time_passed(datetime(29, 0));
+        write_record(policy.as_mut(), 10);
+        assert!(!policy.rotation_necessary());
+        write_record(policy.as_mut(), 10);
+        assert!(policy.rotation_necessary());
+    }
+}
@@ -0,0 +1,119 @@
+use std::{
+    ffi::OsString,
+    io::Read,
+    path::Path,
+    process::{Command, Stdio},
+    sync::mpsc,
+    time::{Duration, Instant},
+};
+
+// the name of the environment variable with the path of the rotated file
+const ENV_ROTATED_FILE: &str = "ROTATED_LOG_FILE";
+// the argument that is replaced by the path of the rotated file
+const PATH_PLACEHOLDER: &str = "{path}";
+// how often the command is checked for completion
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+// how much of the output on stderr is reported
+const MAX_STDERR_SIZE: u64 = 4096;
+
+/// An external command that is run after each rotation.
+pub(crate) struct PostrotateCommand {
+    pub(crate) argv: Vec<OsString>,
+    pub(crate) timeout: Duration,
+}
+
+impl PostrotateCommand {
+    /// Runs the command for the rotated file, and reports failures, timeouts and
+    /// the output on stderr to stderr.
+    pub(crate) fn run(&self, rotated: &Path) {
+        let o_message = match self.run_impl(rotated) {
+            Ok((o_failure, stderr)) => {
+                let stderr = stderr.trim_end();
+                match (o_failure, stderr.is_empty()) {
+                    (None, true) => None,
+                    (None, false) => Some(format!("wrote to stderr: {}", stderr)),
+                    (Some(failure), true) => Some(failure),
+                    (Some(failure), false) => Some(format!("{}, stderr: {}", failure, stderr)),
+                }
+            }
+            Err(e) => Some(format!("failed with {}", e)),
+        };
+        if let Some(message) = o_message {
+            eprintln!(
+                "[flexi_logger] postrotate command {:?} for \"{}\" {}",
+                self.argv[0],
+                rotated.display(),
+                message
+            );
+        }
+    }
+
+    // returns a description of the failure, if any, and the captured stderr
+    fn run_impl(&self, rotated: &Path) -> std::io::Result<(Option<String>, String)> {
+        let args = self.argv[1..].iter().map(|arg| {
+            if arg == PATH_PLACEHOLDER {
+                rotated.as_os_str()
+            } else {
+                arg.as_os_str()
+            }
+        });
+        let mut child = Command::new(&self.argv[0])
+            .args(args)
+            .env(ENV_ROTATED_FILE, rotated)
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::piped())
+            .spawn()?;
+
+        // read stderr in the background, so that the command cannot block on a full pipe;
+        // the rest beyond the reported size is discarded
+        let o_child_stderr = child.stderr.take();
+        let (sender, receiver) = mpsc::channel();
+        std::thread::Builder::new()
+            .name("flexi_logger-postrotate".to_string())
+            .spawn(move || {
+                let mut stderr = Vec::new();
+                if let Some(mut child_stderr) = o_child_stderr {
+                    (&mut child_stderr)
+                        .take(MAX_STDERR_SIZE)
+                        .read_to_end(&mut stderr)
+                        .ok();
+                    let discarded = std::io::copy(&mut child_stderr, &mut std::io::sink());
+                    if discarded.is_ok_and(|discarded| discarded > 0) {
+                        stderr.extend_from_slice(b" [truncated]");
+                    }
+                }
+                sender
+                    .send(String::from_utf8_lossy(&stderr).into_owned())
+                    .ok();
+            })?;
+
+        let deadline = Instant::now() + self.timeout;
+        let o_failure = loop {
+            if let Some(status) = child.try_wait()? {
+                break if status.success() {
+                    None
+                } else {
+                    Some(format!("failed with {}", status))
+                };
+            }
+            if Instant::now() >= deadline {
+                child.kill().ok();
+                child.wait()?;
+                break Some(format!(
+                    "was killed after the timeout of {:?}",
+                    self.timeout
+                ));
+            }
+            std::thread::sleep(POLL_INTERVAL);
+        };
+        // a process that was started by the command can still hold stderr open;
+        // the reader is then left behind rather than waited for beyond the timeout,
+        // but a killed command gets a moment to let its last output be read
+        let wait = deadline
+            .saturating_duration_since(Instant::now())
+            .max(POLL_INTERVAL);
+        let stderr = receiver.recv_timeout(wait).unwrap_or_default();
+        Ok((o_failure, stderr))
+    }
+}
@@ -0,0 +1,308 @@
+use chrono::{
+    format::Parsed, DateTime, Datelike, Duration, FixedOffset, NaiveDate, NaiveDateTime, NaiveTime,
+    Timelike, Utc, Weekday,
+};
+
+/// The time period after which a new log file is started.
+///
+/// The period also determines the date infix in the file name.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum RotationPeriod {
+    /// Rotates every N minutes, counted from midnight. Files are named like `foo_r2021-03-28_13-45.log`.
+    ///
+    /// If N does not divide a day evenly, the last period of the day is shorter.
+    Minutes(u32),
+    /// Rotates every hour. Files are named like `foo_r2021-03-28_13.log`.
+    Hourly,
+    /// Rotates every day. Files are named like `foo_r2021-03-28.log`. This is the default.
+    #[default]
+    Daily,
+    /// Rotates every Monday. Files are named like `foo_r2021-W12.log`, using the ISO week.
+    Weekly,
+    /// Rotates on the first day of every month. Files are named like `foo_r2021-03.log`.
+    Monthly,
+}
+
+impl RotationPeriod {
+    /// Shifts the given time back by the daily rotation time, so that the rotation happens
+    /// at midnight of the shifted time, and the date of the shifted time is the business day.
+    ///
+    /// Only daily, weekly and monthly rotation are affected.
+    pub(crate) fn to_business_time(
+        self,
+        now: NaiveDateTime,
+        rotate_at: NaiveTime,
+    ) -> NaiveDateTime {
+        match self {
+            Self::Minutes(_) | Self::Hourly => now,
+            Self::Daily | Self::Weekly | Self::Monthly => now - (rotate_at - NaiveTime::MIN),
+        }
+    }
+
+    /// Reverses [`to_business_time`](Self::to_business_time), e.g. to get the wall clock time
+    /// at which a period starts.
+    pub(crate) fn to_wall_time(
+        self,
+        business_time: NaiveDateTime,
+        rotate_at: NaiveTime,
+    ) -> NaiveDateTime {
+        match self {
+            Self::Minutes(_) | Self::Hourly => business_time,
+            Self::Daily | Self::Weekly | Self::Monthly => {
+                business_time + (rotate_at - NaiveTime::MIN)
+            }
+        }
+    }
+
+    /// The start of the period that contains the given time.
+    pub(crate) fn period_start(self, now: NaiveDateTime) -> NaiveDateTime {
+        let date = now.date();
+        match self {
+            Self::Minutes(n) => {
+                let n = n.max(1);
+                let minutes = now.hour() * 60 + now.minute();
+                let start = minutes - minutes % n;
+                date.and_time(NaiveTime::from_hms_opt(start / 60, start % 60, 0).unwrap())
+            }
+            Self::Hourly => date.and_time(NaiveTime::from_hms_opt(now.hour(), 0, 0).unwrap()),
+            Self::Daily => date.and_time(NaiveTime::MIN),
+            Self::Weekly => {
+                let monday =
+                    date - Duration::days(i64::from(date.weekday().num_days_from_monday()));
+                monday.and_time(NaiveTime::MIN)
+            }
+            Self::Monthly => NaiveDate::from_ymd_opt(date.year(), date.month(), 1)
+                .unwrap()
+                .and_time(NaiveTime::MIN),
+        }
+    }
+
+    /// The end of the period that starts at the given time, i.e. the start of the next period.
+    pub(crate) fn period_end(self, period_start: NaiveDateTime) -> NaiveDateTime {
+        match self {
+            Self::Minutes(n) => {
+                let end = period_start + Duration::minutes(i64::from(n.max(1)));
+                let next_day = (period_start.date() + Duration::days(1)).and_time(NaiveTime::MIN);
+                end.min(next_day)
+            }
+            Self::Hourly => period_start + Duration::hours(1),
+            Self::Daily => period_start + Duration::days(1),
+            Self::Weekly => period_start + Duration::weeks(1),
+            Self::Monthly => {
+                let date = period_start.date();
+                let (year, month) = if date.month() == 12 {
+                    (date.year() + 1, 1)
+                } else {
+                    (date.year(), date.month() + 1)
+                };
+                NaiveDate::from_ymd_opt(year, month, 1)
+                    .unwrap()
+                    .and_time(NaiveTime::MIN)
+            }
+        }
+    }
+
+    /// The `strftime` format of the date infix in the file name.
+    pub(crate) const fn date_format(self) -> &'static str {
+        match self {
+            Self::Minutes(_) => "%Y-%m-%d_%H-%M",
+            Self::Hourly => "%Y-%m-%d_%H",
+            Self::Daily => "%Y-%m-%d",
+            Self::Weekly => "%G-W%V",
+            Self::Monthly => "%Y-%m",
+        }
+    }
+}
+
+/// The timezone in which the rotation boundaries and the dates in the file names are computed.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum RotationTimezone {
+    /// The local timezone of the system. This is the default.
+    #[default]
+    Local,
+    /// UTC, so that hosts in different timezones rotate at the same instant.
+    Utc,
+    /// A fixed offset from UTC.
+    Fixed(FixedOffset),
+    /// A named timezone from the IANA database, which respects daylight saving time.
+    ///
+    /// Only available with the `chrono-tz` feature.
+    #[cfg(feature = "chrono-tz")]
+    Named(chrono_tz::Tz),
+}
+
+impl RotationTimezone {
+    /// The wall clock time in this timezone at the given instant.
+    pub(crate) fn naive_local(self, now: DateTime<Utc>) -> NaiveDateTime {
+        match self {
+            Self::Local => now.with_timezone(&chrono::Local).naive_local(),
+            Self::Utc => now.naive_utc(),
+            Self::Fixed(offset) => now.with_timezone(&offset).naive_local(),
+            #[cfg(feature = "chrono-tz")]
+            Self::Named(tz) => now.with_timezone(&tz).naive_local(),
+        }
+    }
+
+    /// The given instant in this timezone, with its offset from UTC.
+    pub(crate) fn with_offset(self, now: DateTime<Utc>) -> DateTime<FixedOffset> {
+        match self {
+            Self::Local => now.with_timezone(&chrono::Local).fixed_offset(),
+            Self::Utc => now.fixed_offset(),
+            Self::Fixed(offset) => now.with_timezone(&offset),
+            #[cfg(feature = "chrono-tz")]
+            Self::Named(tz) => now.with_timezone(&tz).fixed_offset(),
+        }
+    }
+}
+
+/// Converts the parsed fields to a date and time, filling in the fields
+/// that are not contained in the date infix with their smallest value.
+pub(crate) fn to_naive_datetime(parsed: &mut Parsed) -> Option<NaiveDateTime> {
+    if parsed.isoweek().is_some() && parsed.weekday().is_none() {
+        parsed.set_weekday(Weekday::Mon).ok()?;
+    }
+    if parsed.month().is_some() && parsed.day().is_none() {
+        parsed.set_day(1).ok()?;
+    }
+    if parsed.hour_div_12().is_none() {
+        parsed.set_hour(0).ok()?;
+    }
+    if parsed.minute().is_none() {
+        parsed.set_minute(0).ok()?;
+    }
+    let date = parsed.to_naive_date().ok()?;
+    let time = parsed.to_naive_time().ok()?;
+    Some(date.and_time(time))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::RotationPeriod;
+    use chrono::{NaiveDate, NaiveDateTime, NaiveTime};
+
+    fn datetime(year: i32, month: u32, day: u32, hour: u32, minute: u32) -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(year, month, day)
+            .unwrap()
+            .and_hms_opt(hour, minute, 0)
+            .unwrap()
+    }
+
+    // checks the period that contains `now`, and the file name date of its start
+    fn assert_period(
+        rotation: RotationPeriod,
+        now: NaiveDateTime,
+        start: NaiveDateTime,
+        end: NaiveDateTime,
+        date_infix: &str,
+    ) {
+        assert_eq!(rotation.period_start(now), start);
+        assert_eq!(rotation.period_end(start), end);
+        assert_eq!(start.format(rotation.date_format()).to_string(), date_infix);
+    }
+
+    #[test]
+    fn minutes() {
+        assert_period(
+            RotationPeriod::Minutes(15),
+            datetime(2021, 3, 28, 13, 59),
+            datetime(2021, 3, 28, 13, 45),
+            datetime(2021, 3, 28, 14, 0),
+            "2021-03-28_13-45",
+        );
+    }
+
+    #[test]
+    fn minutes_with_a_short_last_period() {
+        // 7 does not divide 1440, so the last period of the day has only 5 minutes
+        assert_period(
+            RotationPeriod::Minutes(7),
+            datetime(2021, 3, 28, 23, 57),
+            datetime(2021, 3, 28, 23, 55),
+            datetime(2021, 3, 29, 0, 0),
+            "2021-03-28_23-55",
+        );
+        // and the next day starts again at midnight
+        assert_eq!(
+            RotationPeriod::Minutes(7).period_start(datetime(2021, 3, 29, 0, 3)),
+            datetime(2021, 3, 29, 0, 0)
+        );
+    }
+
+    #[test]
+    fn hourly() {
+        assert_period(
+            RotationPeriod::Hourly,
+            datetime(2021, 3, 28, 23, 30),
+            datetime(2021, 3, 28, 23, 0),
+            datetime(2021, 3, 29, 0, 0),
+            "2021-03-28_23",
+        );
+    }
+
+    #[test]
+    fn daily() {
+        assert_period(
+            RotationPeriod::Daily,
+            datetime(2021, 3, 28, 23, 59),
+            datetime(2021, 3, 28, 0, 0),
+            datetime(2021, 3, 29, 0, 0),
+            "2021-03-28",
+        );
+    }
+
+    #[test]
+    fn weekly() {
+        // 2021-03-28 is a Sunday in ISO week 12
+        assert_period(
+            RotationPeriod::Weekly,
+            datetime(2021, 3, 28, 12, 0),
+            datetime(2021, 3, 22, 0, 0),
+            datetime(2021, 3, 29, 0, 0),
+            "2021-W12",
+        );
+        // the ISO year of the first days of January can be the previous one
+        assert_period(
+            RotationPeriod::Weekly,
+            datetime(2021, 1, 2, 12, 0),
+            datetime(2020, 12, 28, 0, 0),
+            datetime(2021, 1, 4, 0, 0),
+            "2020-W53",
+        );
+    }
+
+    #[test]
+    fn monthly() {
+        assert_period(
+            RotationPeriod::Monthly,
+            datetime(2021, 3, 28, 12, 0),
+            datetime(2021, 3, 1, 0, 0),
+            datetime(2021, 4, 1, 0, 0),
+            "2021-03",
+        );
+    }
+
+    #[test]
+    fn monthly_rolls_over_into_january() {
+        assert_period(
+            RotationPeriod::Monthly,
+            datetime(2021, 12, 31, 23, 59),
+            datetime(2021, 12, 1, 0, 0),
+            datetime(2022, 1, 1, 0, 0),
+            "2021-12",
+        );
+    }
+
+    #[test]
+    fn business_time_round_trip() {
+        let rotate_at = NaiveTime::from_hms_opt(4, 0, 0).unwrap();
+        let now = datetime(2021, 3, 29, 3, 0);
+        let business_time = RotationPeriod::Daily.to_business_time(now, rotate_at);
+        assert_eq!(business_time, datetime(2021, 3, 28, 23, 0));
+        assert_eq!(
+            RotationPeriod::Daily.to_wall_time(business_time, rotate_at),
+            now
+        );
+        // hourly rotation ignores the rotation time
+        assert_eq!(RotationPeriod::Hourly.to_business_time(now, rotate_at), now);
+    }
+}
@@ -0,0 +1,24 @@
+use chrono::NaiveDateTime;
+use std::path::PathBuf;
+
+/// Describes a rotation, see [`on_rotate`](crate::RotateLogWriterBuilder::on_rotate).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RotationEvent {
+    /// The path of the closed log file, which ends with the archive extension
+    /// if the file was compressed.
+    pub closed_path: PathBuf,
+    /// The path of the log file that was opened instead.
+    pub new_path: PathBuf,
+    /// The size of the closed log file in bytes, before compression.
+    pub bytes: u64,
+    /// The number of log records that were written to the closed log file by this writer.
+    pub records: u64,
+    /// The start of the period of the closed log file, in the rotation timezone.
+    ///
+    /// With [`rotate_at`](crate::RotateLogWriterBuilder::rotate_at), periods start at that
+    /// time of day, e.g. at 04:00 on the date in the file name, not at midnight.
+    pub period_start: NaiveDateTime,
+    /// The end of the period of the closed log file, in the rotation timezone,
+    /// i.e. the start of the next period.
+    pub period_end: NaiveDateTime,
+}
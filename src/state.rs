@@ -1,19 +1,58 @@
-use crate::config::{Config, FilenameConfig};
-use chrono::{Date, Datelike, Local};
+use crate::{
+    config::Config,
+    drop_counter::{DropCounter, DropReason},
+    housekeeping::HousekeepingThreadHandle,
+    log_files::list_log_files,
+    naming::get_current_filepath,
+    policy::RotationPolicy,
+    RotationEvent,
+};
+use chrono::{DateTime, NaiveDateTime, Utc};
 use std::{
     fs::OpenOptions,
-    io::{BufWriter, Result as IoResult, Write},
-    path::PathBuf,
+    io::{BufWriter, Error as IoError, ErrorKind, Result as IoResult, Write},
+    path::{Path, PathBuf},
+    sync::Arc,
 };
 
 struct RotationState {
-    created_at: Date<Local>,
+    path: PathBuf,
+    period_start: NaiveDateTime,
+    idx: u32,
+    written_bytes: u64,
+    written_records: u64,
+    // the instant of the last write according to the clock, or the record time
+    o_last_write: Option<DateTime<Utc>>,
+    time_regressed: bool,
 }
 
 impl RotationState {
-    fn rotation_necessary(&self) -> bool {
-        let today = Local::today();
-        self.created_at.num_days_from_ce() != today.num_days_from_ce()
+    fn rotation_necessary(
+        &mut self,
+        config: &Config,
+        now: NaiveDateTime,
+        policy: &mut dyn RotationPolicy,
+    ) -> bool {
+        let period_start = config.rotation.period_start(now);
+        // never go back to an older period, e.g. when the clock was set back
+        if period_start < self.period_start {
+            // records of different threads can arrive slightly out of order,
+            // which is not worth a warning
+            if !self.time_regressed && !config.rotate_by_record_time {
+                eprintln!(
+                    "[flexi_logger] time moved back to {}, before the current period \
+                     starting at {}; continuing to write to \"{}\"",
+                    now,
+                    self.period_start,
+                    self.path.display()
+                );
+                self.time_regressed = true;
+            }
+        } else {
+            self.time_regressed = false;
+        }
+        policy.time_passed(now);
+        policy.rotation_necessary()
     }
 }
 
@@ -24,22 +63,79 @@ enum Inner {
 
 /// The mutable state of a `RotateLogWriter`.
 pub struct State {
-    config: Config,
+    config: Arc<Config>,
     inner: Inner,
+    o_housekeeping_thread_handle: Option<HousekeepingThreadHandle>,
+    drop_counter: Arc<DropCounter>,
+    policy: Box<dyn RotationPolicy>,
+    // the period and index of the latest log file of a previous run of the program
+    o_last_run: Option<(NaiveDateTime, u32)>,
 }
 
 impl State {
-    pub(crate) const fn new(config: Config) -> Self {
-        Self {
-            inner: Inner::Initial,
+    pub(crate) fn try_new(
+        config: Arc<Config>,
+        drop_counter: Arc<DropCounter>,
+        policy: Box<dyn RotationPolicy>,
+    ) -> IoResult<Self> {
+        let o_housekeeping_thread_handle = if config.housekeeping_necessary() {
+            Some(HousekeepingThreadHandle::start(Arc::clone(&config))?)
+        } else {
+            None
+        };
+        let o_last_run = if config.new_file_on_start {
+            list_log_files(&config)?
+                .last()
+                .map(|log_file| (log_file.period_start, log_file.sequence))
+        } else {
+            None
+        };
+        Ok(Self {
             config,
-        }
+            inner: Inner::Initial,
+            o_housekeeping_thread_handle,
+            drop_counter,
+            policy,
+            o_last_run,
+        })
     }
 
-    fn initialize(&mut self) -> IoResult<()> {
+    fn initialize(&mut self, now: NaiveDateTime) -> IoResult<()> {
         if let Inner::Initial = &self.inner {
-            let (log_file, created_at) = open_log_file(&self.config)?;
-            self.inner = Inner::Active(RotationState { created_at }, log_file);
+            let o_stale = if self.config.filename_config.use_current_file {
+                rename_stale_current_file(&self.config, now).unwrap_or_else(|e| {
+                    eprintln!(
+                        "[flexi_logger] renaming the current log file failed with {}",
+                        e
+                    );
+                    None
+                })
+            } else {
+                None
+            };
+            let (path, period_start, idx) = choose_log_file(
+                &self.config,
+                now,
+                None,
+                self.o_last_run,
+                self.policy.as_mut(),
+            );
+            let (log_file, rotation_state) =
+                open_log_file(&self.config, path, period_start, idx, self.policy.as_mut())?;
+            if let Some(handle) = &self.o_housekeeping_thread_handle {
+                let o_rotated = o_stale.map(|(closed_path, period_start, bytes)| RotationEvent {
+                    closed_path,
+                    new_path: rotation_state.path.clone(),
+                    bytes,
+                    records: 0,
+                    period_start: self.config.wall_time(period_start),
+                    period_end: self
+                        .config
+                        .wall_time(self.config.rotation.period_end(period_start)),
+                });
+                handle.act(o_rotated, rotation_state.path.clone());
+            }
+            self.inner = Inner::Active(rotation_state, log_file);
         }
         Ok(())
     }
@@ -53,61 +149,342 @@ impl State {
     }
 
     #[inline]
-    fn mount_next_linewriter_if_necessary(&mut self) -> IoResult<()> {
+    fn mount_next_linewriter_if_necessary(&mut self, now: NaiveDateTime) -> IoResult<()> {
         if let Inner::Active(rotation_state, file) = &mut self.inner {
-            if rotation_state.rotation_necessary() {
-                let (log_file, created_at) = open_log_file(&self.config)?;
+            if rotation_state.rotation_necessary(&self.config, now, self.policy.as_mut()) {
+                let use_current_file = self.config.filename_config.use_current_file;
+                let closed_path = if use_current_file {
+                    rename_current_file(&self.config, rotation_state, file)?
+                } else {
+                    rotation_state.path.clone()
+                };
+                let (path, period_start, idx) = choose_log_file(
+                    &self.config,
+                    now,
+                    Some(rotation_state),
+                    None,
+                    self.policy.as_mut(),
+                );
+                if path == closed_path {
+                    // the naming cannot tell the files of a period apart, e.g. a custom naming
+                    // scheme that ignores the sequence number; the current file is continued,
+                    // since the housekeeping would otherwise take it away from the writer
+                    return Ok(());
+                }
+                let (log_file, new_rotation_state) =
+                    open_log_file(&self.config, path, period_start, idx, self.policy.as_mut())?;
                 *file = log_file;
-                rotation_state.created_at = created_at;
+                let old_rotation_state = std::mem::replace(rotation_state, new_rotation_state);
+                if let Some(handle) = &self.o_housekeeping_thread_handle {
+                    let event = RotationEvent {
+                        closed_path,
+                        new_path: rotation_state.path.clone(),
+                        bytes: old_rotation_state.written_bytes,
+                        records: old_rotation_state.written_records,
+                        period_start: self.config.wall_time(old_rotation_state.period_start),
+                        period_end: self.config.wall_time(
+                            self.config
+                                .rotation
+                                .period_end(old_rotation_state.period_start),
+                        ),
+                    };
+                    handle.act(Some(event), rotation_state.path.clone());
+                }
             }
         }
         Ok(())
     }
 
-    pub(crate) fn write_buffer(&mut self, buf: &[u8]) -> IoResult<()> {
-        self.initialize()?;
+    /// Writes a log line, rotating first if necessary.
+    ///
+    /// `record_time` is the timestamp of the log record, which is used for the rotation
+    /// decision instead of the clock if so configured.
+    pub(crate) fn write_buffer(&mut self, buf: &[u8], record_time: DateTime<Utc>) -> IoResult<()> {
+        let instant = if self.config.rotate_by_record_time {
+            record_time
+        } else {
+            self.config.clock.now()
+        };
+        self.write_buffer_impl(buf, instant)
+            .inspect_err(|_| self.drop_counter.record_drop(DropReason::IoError))
+    }
+
+    fn write_buffer_impl(&mut self, buf: &[u8], instant: DateTime<Utc>) -> IoResult<()> {
+        let now = self.config.rotation_time(instant);
+        self.initialize(now)?;
         // rotate if necessary
-        self.mount_next_linewriter_if_necessary()
+        self.mount_next_linewriter_if_necessary(now)
             .unwrap_or_else(|e| {
                 eprintln!("[flexi_logger] opening file failed with {}", e);
             });
 
-        if let Inner::Active(_rotation_state, log_file) = &mut self.inner {
+        if let Inner::Active(rotation_state, log_file) = &mut self.inner {
+            // report records that were dropped before this one
+            if let Some(pending) = self.drop_counter.take_pending() {
+                let report = pending.report(self.config.timezone, self.config.line_ending);
+                if let Err(e) = log_file.write_all(&report) {
+                    self.drop_counter.restore_pending(pending);
+                    return Err(e);
+                }
+                rotation_state.written_bytes += report.len() as u64;
+                self.policy.bytes_written(report.len() as u64);
+            }
             log_file.write_all(buf)?;
+            rotation_state.written_bytes += buf.len() as u64;
+            rotation_state.written_records += 1;
+            rotation_state.o_last_write = Some(instant);
+            self.policy.bytes_written(buf.len() as u64);
+            self.policy.record_written();
         }
         Ok(())
     }
+
+    /// Rotates if the current period is over, even if nothing is written.
+    ///
+    /// Nothing happens if no log file was opened yet.
+    pub(crate) fn rotate_if_necessary(&mut self) {
+        let now = self.config.now();
+        self.mount_next_linewriter_if_necessary(now)
+            .unwrap_or_else(|e| {
+                eprintln!("[flexi_logger] opening file failed with {}", e);
+            });
+    }
+
+    pub(crate) fn config(&self) -> &Arc<Config> {
+        &self.config
+    }
+
+    /// Flushes the log file, and waits for the pending housekeeping to finish.
+    pub(crate) fn shutdown(&mut self) {
+        self.flush().ok();
+        if self.config.filename_config.use_current_file {
+            if let Inner::Active(rotation_state, _) = &self.inner {
+                if let Some(last_write) = rotation_state.o_last_write {
+                    // lets a restart find the period of the current file, also with a mock clock
+                    set_modified(&rotation_state.path, last_write).unwrap_or_else(|e| {
+                        eprintln!(
+                            "[flexi_logger] setting the modification time of \"{}\" failed with {}",
+                            rotation_state.path.display(),
+                            e
+                        );
+                    });
+                }
+            }
+        }
+        if let Some(handle) = self.o_housekeeping_thread_handle.take() {
+            handle.shutdown();
+        }
+    }
 }
 
-fn get_filepath(date: Date<Local>, config: &FilenameConfig) -> PathBuf {
-    let date_infix = date.format("%Y-%m-%d").to_string();
-    let s_filename = format!("{}_r{}.{}", config.file_basename, date_infix, config.suffix);
-    let mut p_path = config.directory.to_path_buf();
-    p_path.push(s_filename);
-    p_path
+/// Chooses the path, period start and index of the log file to write to.
+fn choose_log_file(
+    config: &Config,
+    now: NaiveDateTime,
+    o_prev_state: Option<&RotationState>,
+    o_last_run: Option<(NaiveDateTime, u32)>,
+    policy: &mut dyn RotationPolicy,
+) -> (PathBuf, NaiveDateTime, u32) {
+    let mut period_start = config.rotation.period_start(now);
+    if let Some(prev_state) = o_prev_state {
+        period_start = period_start.max(prev_state.period_start);
+    }
+
+    // within the same period, continue with the next index; a new period starts again with 0
+    let mut idx = match (o_prev_state, o_last_run) {
+        (Some(prev_state), _) if prev_state.period_start == period_start => prev_state.idx + 1,
+        (None, Some((last_start, last_idx))) if last_start == period_start => last_idx + 1,
+        _ => 0,
+    };
+    let mut p_path = if config.filename_config.use_current_file {
+        get_current_filepath(&config.filename_config)
+    } else {
+        config.log_file_path(period_start, idx)
+    };
+
+    // skip files that the policy considers full, or that are already compressed, e.g. from
+    // a previous run of the program, and on startup all existing files if a new file is wanted
+    let must_be_new = config.new_file_on_start && o_prev_state.is_none();
+    policy.time_passed(now);
+    if !config.filename_config.use_current_file {
+        while (must_be_new && p_path.exists())
+            || config
+                .o_compression
+                .is_some_and(|compression| compression.archive_path(&p_path).exists())
+            // a policy that rotates even an empty file must not lead to an endless loop
+            || (p_path.exists() && {
+                policy.file_opened(period_start, file_size(&p_path));
+                policy.rotation_necessary()
+            })
+        {
+            let next_path = config.log_file_path(period_start, idx + 1);
+            if next_path == p_path {
+                // the naming does not use the index
+                break;
+            }
+            idx += 1;
+            p_path = next_path;
+        }
+    }
+    (p_path, period_start, idx)
 }
 
-fn open_log_file(config: &Config) -> IoResult<(Box<dyn Write + Send>, Date<Local>)> {
-    let today = Local::today();
-    let p_path = get_filepath(today, &config.filename_config);
+fn open_log_file(
+    config: &Config,
+    p_path: PathBuf,
+    period_start: NaiveDateTime,
+    idx: u32,
+    policy: &mut dyn RotationPolicy,
+) -> IoResult<(Box<dyn Write + Send>, RotationState)> {
+    let written_bytes = file_size(&p_path);
+    policy.file_opened(period_start, written_bytes);
+
     if config.print_message {
         println!("Log is written to {}", &p_path.display());
     }
+    if let Some(parent) = p_path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
     #[cfg(target_os = "linux")]
     if let Some(ref link) = config.o_create_symlink {
         self::linux::create_symlink(link, &p_path);
     }
-    let log_file = OpenOptions::new()
-        .write(true)
-        .create(true)
-        .append(true)
-        .open(&p_path)?;
+    let log_file = OpenOptions::new().create(true).append(true).open(&p_path)?;
     let w: Box<dyn Write + Send> = if let Some(capacity) = config.o_buffersize {
         Box::new(BufWriter::with_capacity(capacity, log_file))
     } else {
         Box::new(log_file)
     };
-    Ok((w, today))
+    Ok((
+        w,
+        RotationState {
+            path: p_path,
+            period_start,
+            idx,
+            written_bytes,
+            written_records: 0,
+            o_last_write: None,
+            time_regressed: false,
+        },
+    ))
+}
+
+/// Closes the current log file and renames it to its dated name.
+///
+/// Returns the new path of the closed file. If the renaming fails, the current
+/// log file is opened again, so that logging continues. Until a file is open again,
+/// writing fails, so that the records are counted as dropped.
+fn rename_current_file(
+    config: &Config,
+    rotation_state: &mut RotationState,
+    file: &mut Box<dyn Write + Send>,
+) -> IoResult<PathBuf> {
+    file.flush()?;
+    // close the file before renaming it
+    *file = Box::new(ClosedFile);
+    match rename_to_dated_path(
+        config,
+        &rotation_state.path,
+        rotation_state.period_start,
+        rotation_state.idx,
+    ) {
+        Ok((dated_path, idx)) => {
+            rotation_state.idx = idx;
+            Ok(dated_path)
+        }
+        Err(e) => {
+            *file = Box::new(
+                OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(&rotation_state.path)?,
+            );
+            Err(e)
+        }
+    }
+}
+
+fn set_modified(path: &Path, instant: DateTime<Utc>) -> IoResult<()> {
+    OpenOptions::new()
+        .append(true)
+        .open(path)?
+        .set_modified(instant.into())
+}
+
+/// Stands in for a log file that is closed, and fails to write.
+struct ClosedFile;
+
+impl Write for ClosedFile {
+    fn write(&mut self, _buf: &[u8]) -> IoResult<usize> {
+        Err(IoError::new(
+            ErrorKind::NotConnected,
+            "the log file is closed",
+        ))
+    }
+
+    fn flush(&mut self) -> IoResult<()> {
+        Ok(())
+    }
+}
+
+/// Renames a current log file that was left over from an earlier period, or from any
+/// earlier run of the program if a new file is wanted on each start, to its dated name.
+///
+/// The period of the file is derived from its modification time, which is set to the time
+/// of the last write according to the clock on shutdown.
+/// Returns the new path, the start of the period and the size of the file.
+fn rename_stale_current_file(
+    config: &Config,
+    now: NaiveDateTime,
+) -> IoResult<Option<(PathBuf, NaiveDateTime, u64)>> {
+    let current_path = get_current_filepath(&config.filename_config);
+    let metadata = match std::fs::metadata(&current_path) {
+        Ok(metadata) if metadata.len() > 0 => metadata,
+        _ => return Ok(None),
+    };
+    let modified = config.rotation_time(DateTime::<Utc>::from(metadata.modified()?));
+    let period_start = config.rotation.period_start(modified);
+    if period_start >= config.rotation.period_start(now) && !config.new_file_on_start {
+        return Ok(None);
+    }
+    let (dated_path, _) = rename_to_dated_path(config, &current_path, period_start, 0)?;
+    Ok(Some((dated_path, period_start, metadata.len())))
+}
+
+/// Renames a file to the first free dated name of the period, starting with the given index.
+fn rename_to_dated_path(
+    config: &Config,
+    path: &Path,
+    period_start: NaiveDateTime,
+    mut idx: u32,
+) -> IoResult<(PathBuf, u32)> {
+    let mut dated_path = config.log_file_path(period_start, idx);
+    // don't overwrite files of a previous run of the program
+    while dated_path.exists()
+        || config
+            .o_compression
+            .is_some_and(|compression| compression.archive_path(&dated_path).exists())
+    {
+        let next_path = config.log_file_path(period_start, idx + 1);
+        if next_path == dated_path {
+            // the naming does not use the index
+            return Err(IoError::new(
+                ErrorKind::AlreadyExists,
+                format!("log file \"{}\" exists already", dated_path.display()),
+            ));
+        }
+        idx += 1;
+        dated_path = next_path;
+    }
+    if let Some(parent) = dated_path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    std::fs::rename(path, &dated_path)?;
+    Ok((dated_path, idx))
+}
+
+fn file_size(path: &Path) -> u64 {
+    std::fs::metadata(path).map_or(0, |m| m.len())
 }
 
 #[cfg(target_os = "linux")]
@@ -126,7 +503,7 @@ mod linux {
         }
 
         // create new symlink
-        if let Err(e) = std::os::unix::fs::symlink(&logfile, link) {
+        if let Err(e) = std::os::unix::fs::symlink(logfile, link) {
             eprintln!(
                 "[flexi_logger] cannot create symlink {:?} for logfile \"{}\" due to {:?}",
                 link,
@@ -136,3 +513,151 @@ mod linux {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{Inner, State};
+    use crate::{
+        config::Config,
+        drop_counter::{DropCounter, DropReason},
+        naming::DefaultNaming,
+        policy::default_policy,
+        MockClock, RotationTimezone,
+    };
+    use chrono::{DateTime, TimeZone, Utc};
+    use std::{
+        io::{Error as IoError, Result as IoResult, Write},
+        path::Path,
+        sync::{
+            atomic::{AtomicBool, Ordering},
+            Arc, Mutex,
+        },
+    };
+
+    // a log file in memory, whose writes fail while `failing` is set
+    #[derive(Clone, Default)]
+    struct TestFile {
+        content: Arc<Mutex<Vec<u8>>>,
+        failing: Arc<AtomicBool>,
+    }
+
+    impl Write for TestFile {
+        fn write(&mut self, buf: &[u8]) -> IoResult<usize> {
+            if self.failing.load(Ordering::Relaxed) {
+                return Err(IoError::other("disk full"));
+            }
+            self.content.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> IoResult<()> {
+            Ok(())
+        }
+    }
+
+    fn now() -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2021, 3, 28, 12, 0, 0).unwrap()
+    }
+
+    // a configuration for daily log files named like foo_r2021-03-28.log, in UTC
+    fn test_config(dir: &Path, clock: &MockClock) -> Config {
+        let mut config = Config {
+            clock: Arc::new(clock.clone()),
+            timezone: RotationTimezone::Utc,
+            ..Config::default()
+        };
+        config.filename_config.directory = dir.to_path_buf();
+        config.filename_config.file_basename = "foo".to_string();
+        config.naming = Box::new(DefaultNaming::new(
+            config.filename_config.clone(),
+            config.rotation,
+        ));
+        config
+    }
+
+    fn start(config: Config) -> (State, Arc<DropCounter>) {
+        let config = Arc::new(config);
+        let drop_counter = Arc::new(DropCounter::new(Arc::clone(&config.clock)));
+        let policy = default_policy(config.rotation, None);
+        let state = State::try_new(config, Arc::clone(&drop_counter), policy).unwrap();
+        (state, drop_counter)
+    }
+
+    // a state whose log file is replaced by a `TestFile` after the first write
+    fn new_state(dir: &Path) -> (State, Arc<DropCounter>, TestFile) {
+        let (mut state, drop_counter) = start(test_config(dir, &MockClock::new(now())));
+        state.write_buffer(b"first\n", now()).unwrap();
+        let test_file = TestFile::default();
+        if let Inner::Active(_, file) = &mut state.inner {
+            *file = Box::new(test_file.clone());
+        }
+        (state, drop_counter, test_file)
+    }
+
+    #[test]
+    fn drop_report_is_written_before_the_next_record() {
+        let dir = tempfile::tempdir().unwrap();
+        let (mut state, drop_counter, test_file) = new_state(dir.path());
+        drop_counter.record_drop(DropReason::Overflow);
+        drop_counter.record_drop(DropReason::Overflow);
+
+        state.write_buffer(b"next\n", now()).unwrap();
+
+        let content = String::from_utf8(test_file.content.lock().unwrap().clone()).unwrap();
+        let lines: Vec<&str> = content.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].starts_with("[flexi_logger] 2 records dropped between "));
+        assert_eq!(lines[1], "next");
+        assert!(drop_counter.take_pending().is_none());
+    }
+
+    #[test]
+    fn drop_report_is_kept_if_writing_it_fails() {
+        let dir = tempfile::tempdir().unwrap();
+        let (mut state, drop_counter, test_file) = new_state(dir.path());
+        drop_counter.record_drop(DropReason::Overflow);
+        test_file.failing.store(true, Ordering::Relaxed);
+
+        assert!(state.write_buffer(b"lost\n", now()).is_err());
+        test_file.failing.store(false, Ordering::Relaxed);
+        state.write_buffer(b"next\n", now()).unwrap();
+
+        let content = String::from_utf8(test_file.content.lock().unwrap().clone()).unwrap();
+        let lines: Vec<&str> = content.lines().collect();
+        assert_eq!(lines.len(), 2);
+        // the failed record is reported together with the earlier drop
+        assert!(lines[0].starts_with("[flexi_logger] 2 records dropped between "));
+        assert_eq!(lines[1], "next");
+        let dropped_records = drop_counter.dropped_records();
+        assert_eq!(dropped_records.overflow, 1);
+        assert_eq!(dropped_records.io_error, 1);
+    }
+
+    #[test]
+    fn record_time_decides_the_period() {
+        let dir = tempfile::tempdir().unwrap();
+        // the clock has already passed midnight
+        let clock = MockClock::new(Utc.with_ymd_and_hms(2021, 3, 29, 0, 0, 5).unwrap());
+        let config = Config {
+            rotate_by_record_time: true,
+            ..test_config(dir.path(), &clock)
+        };
+        let (mut state, _) = start(config);
+
+        let before_midnight = Utc.with_ymd_and_hms(2021, 3, 28, 23, 59, 59).unwrap();
+        state
+            .write_buffer(b"before midnight\n", before_midnight)
+            .unwrap();
+        let after_midnight = Utc.with_ymd_and_hms(2021, 3, 29, 0, 0, 1).unwrap();
+        state
+            .write_buffer(b"after midnight\n", after_midnight)
+            .unwrap();
+        // a late record does not go back to the closed file
+        state.write_buffer(b"late\n", before_midnight).unwrap();
+        state.shutdown();
+
+        let read_log = |name: &str| std::fs::read_to_string(dir.path().join(name)).unwrap();
+        assert_eq!(read_log("foo_r2021-03-28.log"), "before midnight\n");
+        assert_eq!(read_log("foo_r2021-03-29.log"), "after midnight\nlate\n");
+    }
+}
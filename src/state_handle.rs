@@ -0,0 +1,253 @@
+use crate::{
+    drop_counter::{DropCounter, DropReason},
+    state::State,
+    timer::TimerThreadHandle,
+    OverflowPolicy,
+};
+use chrono::{DateTime, Utc};
+use crossbeam_channel::{Receiver, Sender, TrySendError};
+use std::{
+    io::Result as IoResult,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    thread::JoinHandle,
+};
+
+/// Gives access to the [`State`], either directly or through a writer thread.
+pub(crate) enum StateHandle {
+    Sync(SyncHandle),
+    Async(AsyncHandle),
+}
+
+impl StateHandle {
+    pub(crate) fn write_buffer(&self, buf: &[u8], record_time: DateTime<Utc>) -> IoResult<()> {
+        match self {
+            Self::Sync(handle) => handle
+                .am_state
+                .lock()
+                .unwrap()
+                .write_buffer(buf, record_time),
+            Self::Async(handle) => {
+                handle.send((buf.to_vec(), record_time));
+                Ok(())
+            }
+        }
+    }
+
+    pub(crate) fn flush(&self) -> IoResult<()> {
+        match self {
+            Self::Sync(handle) => {
+                if let Ok(mut state) = handle.am_state.lock() {
+                    state.flush()
+                } else {
+                    Ok(())
+                }
+            }
+            Self::Async(handle) => {
+                handle.flush();
+                Ok(())
+            }
+        }
+    }
+
+    pub(crate) fn shutdown(&self) {
+        match self {
+            Self::Sync(handle) => handle.shutdown(),
+            Self::Async(handle) => handle.shutdown(),
+        }
+    }
+}
+
+/// Gives the logging threads access to the [`State`] through a mutex,
+/// in [`WriteMode::Direct`](crate::WriteMode::Direct).
+pub(crate) struct SyncHandle {
+    am_state: Arc<Mutex<State>>,
+    mo_timer_thread_handle: Mutex<Option<TimerThreadHandle>>,
+}
+
+impl SyncHandle {
+    pub(crate) fn start(state: State) -> IoResult<Self> {
+        let rotate_on_timer = state.config().rotate_on_timer;
+        let config = Arc::clone(state.config());
+        let am_state = Arc::new(Mutex::new(state));
+        let o_timer_thread_handle = if rotate_on_timer {
+            Some(TimerThreadHandle::start(config, Arc::clone(&am_state))?)
+        } else {
+            None
+        };
+        Ok(Self {
+            am_state,
+            mo_timer_thread_handle: Mutex::new(o_timer_thread_handle),
+        })
+    }
+
+    fn shutdown(&self) {
+        if let Ok(mut o_timer_thread_handle) = self.mo_timer_thread_handle.lock() {
+            if let Some(timer_thread_handle) = o_timer_thread_handle.take() {
+                timer_thread_handle.shutdown();
+            }
+        }
+        // do nothing in case of poison errors
+        if let Ok(mut state) = self.am_state.lock() {
+            state.shutdown();
+        }
+    }
+}
+
+/// A formatted log line, with the timestamp of its record.
+type LogLine = (Vec<u8>, DateTime<Utc>);
+
+enum MessageToWriterThread {
+    Flush(Sender<()>),
+    Die,
+}
+
+/// Handle to the thread that owns the [`State`] in [`WriteMode::Async`](crate::WriteMode::Async).
+///
+/// The log lines go over a bounded channel, while flush and shutdown requests go over
+/// a separate channel, so that they are never dropped.
+///
+/// Log lines that arrive after the writer thread has ended are counted as dropped.
+pub(crate) struct AsyncHandle {
+    data_sender: Sender<LogLine>,
+    // only kept with OverflowPolicy::DropOldest, to drop the oldest log line; otherwise
+    // the channel disconnects when the writer thread ends, so that sending cannot block
+    o_data_receiver: Option<Receiver<LogLine>>,
+    control_sender: Sender<MessageToWriterThread>,
+    overflow: OverflowPolicy,
+    drop_counter: Arc<DropCounter>,
+    shut_down: AtomicBool,
+    mo_join_handle: Mutex<Option<JoinHandle<()>>>,
+}
+
+impl AsyncHandle {
+    pub(crate) fn start(
+        state: State,
+        capacity: usize,
+        overflow: OverflowPolicy,
+        drop_counter: Arc<DropCounter>,
+    ) -> IoResult<Self> {
+        let (data_sender, data_receiver) = crossbeam_channel::bounded(capacity.max(1));
+        let (control_sender, control_receiver) = crossbeam_channel::unbounded();
+        let o_data_receiver = match overflow {
+            OverflowPolicy::DropOldest => Some(data_receiver.clone()),
+            OverflowPolicy::Block | OverflowPolicy::DropNewest => None,
+        };
+        let join_handle = std::thread::Builder::new()
+            .name("flexi_logger-async_writer".to_string())
+            .spawn(move || run(state, &data_receiver, &control_receiver))?;
+        Ok(Self {
+            data_sender,
+            o_data_receiver,
+            control_sender,
+            overflow,
+            drop_counter,
+            shut_down: AtomicBool::new(false),
+            mo_join_handle: Mutex::new(Some(join_handle)),
+        })
+    }
+
+    fn send(&self, log_line: LogLine) {
+        if self.shut_down.load(Ordering::Acquire) {
+            // nobody would write the log line anymore
+            self.drop_counter.record_drop(DropReason::IoError);
+            return;
+        }
+        match self.overflow {
+            OverflowPolicy::Block => {
+                if self.data_sender.send(log_line).is_err() {
+                    // the writer thread ended unexpectedly
+                    self.drop_counter.record_drop(DropReason::IoError);
+                }
+            }
+            OverflowPolicy::DropNewest => match self.data_sender.try_send(log_line) {
+                Ok(()) => {}
+                Err(TrySendError::Full(_)) => self.drop_counter.record_drop(DropReason::Overflow),
+                Err(TrySendError::Disconnected(_)) => {
+                    self.drop_counter.record_drop(DropReason::IoError);
+                }
+            },
+            OverflowPolicy::DropOldest => {
+                let mut log_line = log_line;
+                while let Err(TrySendError::Full(returned)) = self.data_sender.try_send(log_line) {
+                    if let Some(data_receiver) = &self.o_data_receiver {
+                        if data_receiver.try_recv().is_ok() {
+                            self.drop_counter.record_drop(DropReason::Overflow);
+                        }
+                    }
+                    log_line = returned;
+                }
+            }
+        }
+    }
+
+    fn flush(&self) {
+        let (ack_sender, ack_receiver) = crossbeam_channel::bounded(1);
+        if self
+            .control_sender
+            .send(MessageToWriterThread::Flush(ack_sender))
+            .is_ok()
+        {
+            ack_receiver.recv().ok();
+        }
+    }
+
+    fn shutdown(&self) {
+        self.shut_down.store(true, Ordering::Release);
+        self.control_sender.send(MessageToWriterThread::Die).ok();
+        if let Ok(mut o_join_handle) = self.mo_join_handle.lock() {
+            if let Some(join_handle) = o_join_handle.take() {
+                join_handle.join().ok();
+            }
+        }
+    }
+}
+
+fn run(
+    mut state: State,
+    data_receiver: &Receiver<LogLine>,
+    control_receiver: &Receiver<MessageToWriterThread>,
+) {
+    let rotate_on_timer = state.config().rotate_on_timer;
+    let write_buffer = |state: &mut State, (buffer, record_time): LogLine| {
+        state
+            .write_buffer(&buffer, record_time)
+            .unwrap_or_else(|e| crate::write_err(crate::ERR_2, &e));
+    };
+    loop {
+        let timer = if rotate_on_timer {
+            crossbeam_channel::after(state.config().time_to_next_rotation())
+        } else {
+            crossbeam_channel::never()
+        };
+        crossbeam_channel::select! {
+            recv(data_receiver) -> log_line => {
+                if let Ok(log_line) = log_line {
+                    write_buffer(&mut state, log_line);
+                } else {
+                    state.shutdown();
+                    return;
+                }
+            }
+            recv(control_receiver) -> message => {
+                // first write what was logged before the request
+                for log_line in data_receiver.try_iter() {
+                    write_buffer(&mut state, log_line);
+                }
+                match message {
+                    Ok(MessageToWriterThread::Flush(ack_sender)) => {
+                        state.flush().unwrap_or_else(|e| crate::write_err(crate::ERR_2, &e));
+                        ack_sender.send(()).ok();
+                    }
+                    Ok(MessageToWriterThread::Die) | Err(_) => {
+                        state.shutdown();
+                        return;
+                    }
+                }
+            }
+            recv(timer) -> _ => state.rotate_if_necessary(),
+        }
+    }
+}
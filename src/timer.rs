@@ -0,0 +1,43 @@
+use crate::{config::Config, state::State};
+use std::{
+    io::Result as IoResult,
+    sync::{
+        mpsc::{RecvTimeoutError, Sender},
+        Arc, Mutex,
+    },
+    thread::JoinHandle,
+};
+
+/// Handle to the thread that rotates at the period boundaries in
+/// [`WriteMode::Direct`](crate::WriteMode::Direct), even if nothing is logged.
+pub(crate) struct TimerThreadHandle {
+    sender: Sender<()>,
+    join_handle: JoinHandle<()>,
+}
+
+impl TimerThreadHandle {
+    pub(crate) fn start(config: Arc<Config>, am_state: Arc<Mutex<State>>) -> IoResult<Self> {
+        let (sender, receiver) = std::sync::mpsc::channel();
+        let join_handle = std::thread::Builder::new()
+            .name("flexi_logger-rotation_timer".to_string())
+            .spawn(move || {
+                // any message, or the disconnection of the sender, ends the thread
+                while let Err(RecvTimeoutError::Timeout) =
+                    receiver.recv_timeout(config.time_to_next_rotation())
+                {
+                    if let Ok(mut state) = am_state.lock() {
+                        state.rotate_if_necessary();
+                    }
+                }
+            })?;
+        Ok(Self {
+            sender,
+            join_handle,
+        })
+    }
+
+    pub(crate) fn shutdown(self) {
+        self.sender.send(()).ok();
+        self.join_handle.join().ok();
+    }
+}
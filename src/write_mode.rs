@@ -0,0 +1,27 @@
+/// How the [`RotateLogWriter`](crate::RotateLogWriter) writes the log lines to the file.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum WriteMode {
+    /// The log lines are written to the file by the logging thread. This is the default.
+    #[default]
+    Direct,
+    /// The log lines are sent over a bounded channel to a dedicated thread,
+    /// which writes them to the file. Slow disks then do not block the logging threads.
+    Async {
+        /// The number of log lines that the channel can hold.
+        capacity: usize,
+        /// What to do when the channel is full.
+        overflow: OverflowPolicy,
+    },
+}
+
+/// What to do when the channel of [`WriteMode::Async`] is full.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum OverflowPolicy {
+    /// The logging thread waits until there is space in the channel. This is the default.
+    #[default]
+    Block,
+    /// The new log line is dropped.
+    DropNewest,
+    /// The oldest log line in the channel is dropped to make space for the new one.
+    DropOldest,
+}
@@ -0,0 +1,106 @@
+use chrono::{Duration, TimeZone, Utc};
+use flexi_logger::{writers::LogWriter, DeferredNow, Record};
+use flexi_logger_rotate_writer::{Compression, MockClock, RotateLogWriter, RotationTimezone};
+use std::{io::Read, path::Path};
+
+fn write_line(writer: &RotateLogWriter, line: &str) {
+    writer
+        .write(
+            &mut DeferredNow::new(),
+            &Record::builder().args(format_args!("{}", line)).build(),
+        )
+        .unwrap();
+}
+
+fn log_file_names(dir: &Path) -> Vec<String> {
+    let mut names: Vec<String> = std::fs::read_dir(dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+        .collect();
+    names.sort();
+    names
+}
+
+// writes one line per day, starting on 2021-03-28, and shuts down
+fn write_daily_lines(dir: &Path, compression: Compression, o_keep_files: Option<usize>, days: i64) {
+    let clock = MockClock::new(Utc.with_ymd_and_hms(2021, 3, 28, 12, 0, 0).unwrap());
+    let mut builder = RotateLogWriter::builder()
+        .directory(dir)
+        .basename("foo")
+        .timezone(RotationTimezone::Utc)
+        .clock(clock.clone())
+        .compress_rotated(compression);
+    if let Some(keep_files) = o_keep_files {
+        builder = builder.keep_files(keep_files);
+    }
+    let writer = builder.try_build().unwrap();
+
+    for day in 0..days {
+        if day > 0 {
+            clock.advance(Duration::days(1));
+        }
+        write_line(&writer, &format!("day {}", day));
+    }
+    writer.shutdown();
+}
+
+fn decompress_gzip(path: &Path) -> String {
+    let mut content = String::new();
+    flate2::read::MultiGzDecoder::new(std::fs::File::open(path).unwrap())
+        .read_to_string(&mut content)
+        .unwrap();
+    content
+}
+
+#[test]
+fn gzip_replaces_the_rotated_file() {
+    let dir = tempfile::tempdir().unwrap();
+    write_daily_lines(dir.path(), Compression::Gzip, None, 2);
+
+    assert_eq!(
+        log_file_names(dir.path()),
+        ["foo_r2021-03-28.log.gz", "foo_r2021-03-29.log"]
+    );
+    let content = decompress_gzip(&dir.path().join("foo_r2021-03-28.log.gz"));
+    assert_eq!(content.lines().count(), 1);
+    assert!(content.ends_with("day 0\n"));
+}
+
+#[test]
+fn gzip_archives_count_for_the_retention() {
+    let dir = tempfile::tempdir().unwrap();
+    write_daily_lines(dir.path(), Compression::Gzip, Some(2), 4);
+
+    assert_eq!(
+        log_file_names(dir.path()),
+        ["foo_r2021-03-30.log.gz", "foo_r2021-03-31.log"]
+    );
+}
+
+#[cfg(feature = "zstd")]
+#[test]
+fn zstd_replaces_the_rotated_file() {
+    let dir = tempfile::tempdir().unwrap();
+    write_daily_lines(dir.path(), Compression::Zstd(0), None, 2);
+
+    assert_eq!(
+        log_file_names(dir.path()),
+        ["foo_r2021-03-28.log.zst", "foo_r2021-03-29.log"]
+    );
+    let archive = std::fs::File::open(dir.path().join("foo_r2021-03-28.log.zst")).unwrap();
+    let content = String::from_utf8(zstd::decode_all(archive).unwrap()).unwrap();
+    assert_eq!(content.lines().count(), 1);
+    assert!(content.ends_with("day 0\n"));
+}
+
+#[cfg(feature = "zstd")]
+#[test]
+fn zstd_archives_count_for_the_retention() {
+    let dir = tempfile::tempdir().unwrap();
+    write_daily_lines(dir.path(), Compression::Zstd(3), Some(2), 4);
+
+    assert_eq!(
+        log_file_names(dir.path()),
+        ["foo_r2021-03-30.log.zst", "foo_r2021-03-31.log"]
+    );
+}
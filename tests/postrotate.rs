@@ -0,0 +1,147 @@
+#![cfg(unix)]
+
+use chrono::{Duration, TimeZone, Utc};
+use flexi_logger::{writers::LogWriter, DeferredNow, Record};
+use flexi_logger_rotate_writer::{MockClock, RotateLogWriter, RotationTimezone};
+use std::{
+    path::Path,
+    process::Command,
+    time::{Duration as StdDuration, Instant},
+};
+
+// set for the copy of the test binary that runs a test whose stderr is checked
+const ENV_CHILD_TEST: &str = "POSTROTATE_CHILD_TEST";
+
+fn write_line(writer: &RotateLogWriter, line: &str) {
+    writer
+        .write(
+            &mut DeferredNow::new(),
+            &Record::builder().args(format_args!("{}", line)).build(),
+        )
+        .unwrap();
+}
+
+// writes a line on two days, so that the file of the first day is rotated, and shuts down
+fn rotate_once(dir: &Path, argv: &[&str], timeout: StdDuration) {
+    let clock = MockClock::new(Utc.with_ymd_and_hms(2021, 3, 28, 12, 0, 0).unwrap());
+    let writer = RotateLogWriter::builder()
+        .directory(dir)
+        .basename("foo")
+        .timezone(RotationTimezone::Utc)
+        .clock(clock.clone())
+        .postrotate_command(argv.iter().copied(), timeout)
+        .try_build()
+        .unwrap();
+
+    write_line(&writer, "first day");
+    clock.advance(Duration::days(1));
+    write_line(&writer, "second day");
+    writer.shutdown();
+}
+
+#[test]
+fn path_placeholder_is_replaced() {
+    let dir = tempfile::tempdir().unwrap();
+    let copy = dir.path().join("copy.txt");
+    rotate_once(
+        dir.path(),
+        &["cp", "{path}", copy.to_str().unwrap()],
+        StdDuration::from_secs(10),
+    );
+
+    assert!(std::fs::read_to_string(copy).unwrap().contains("first day"));
+}
+
+#[test]
+fn path_is_passed_in_the_environment() {
+    let dir = tempfile::tempdir().unwrap();
+    let script = format!(
+        "printf %s \"$ROTATED_LOG_FILE\" > '{}'",
+        dir.path().join("env.txt").display()
+    );
+    rotate_once(
+        dir.path(),
+        &["sh", "-c", &script],
+        StdDuration::from_secs(10),
+    );
+
+    assert_eq!(
+        std::fs::read_to_string(dir.path().join("env.txt")).unwrap(),
+        dir.path().join("foo_r2021-03-28.log").to_str().unwrap()
+    );
+}
+
+#[test]
+fn command_is_killed_after_the_timeout() {
+    let dir = tempfile::tempdir().unwrap();
+    let start = Instant::now();
+    rotate_once(dir.path(), &["sleep", "5"], StdDuration::from_millis(200));
+
+    assert!(start.elapsed() < StdDuration::from_secs(3));
+}
+
+#[test]
+fn background_process_of_the_command_is_not_waited_for() {
+    let dir = tempfile::tempdir().unwrap();
+    let start = Instant::now();
+    // the shell exits at once, but the sleep inherits its stderr
+    rotate_once(
+        dir.path(),
+        &["sh", "-c", "sleep 5 &"],
+        StdDuration::from_millis(500),
+    );
+
+    assert!(start.elapsed() < StdDuration::from_secs(3));
+}
+
+#[test]
+fn failure_and_stderr_are_reported() {
+    if std::env::var_os(ENV_CHILD_TEST).is_some() {
+        let dir = tempfile::tempdir().unwrap();
+        rotate_once(
+            dir.path(),
+            &["sh", "-c", "echo 'disk is full' >&2; exit 3"],
+            StdDuration::from_secs(10),
+        );
+        return;
+    }
+
+    // the report goes to stderr, so it is checked in a copy of this test binary
+    let output = Command::new(std::env::current_exe().unwrap())
+        .args(["--exact", "failure_and_stderr_are_reported", "--nocapture"])
+        .env(ENV_CHILD_TEST, "1")
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("[flexi_logger] postrotate command \"sh\" for"),
+        "{}",
+        stderr
+    );
+    assert!(stderr.contains("failed with exit status: 3"), "{}", stderr);
+    assert!(stderr.contains("stderr: disk is full"), "{}", stderr);
+}
+
+#[test]
+fn reported_stderr_is_capped() {
+    if std::env::var_os(ENV_CHILD_TEST).is_some() {
+        let dir = tempfile::tempdir().unwrap();
+        rotate_once(
+            dir.path(),
+            &["sh", "-c", "head -c 100000 /dev/zero | tr '\\0' x >&2"],
+            StdDuration::from_secs(10),
+        );
+        return;
+    }
+
+    let output = Command::new(std::env::current_exe().unwrap())
+        .args(["--exact", "reported_stderr_is_capped", "--nocapture"])
+        .env(ENV_CHILD_TEST, "1")
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("x [truncated]"), "{}", stderr);
+    assert!(stderr.len() < 10_000, "{}", stderr.len());
+}
@@ -0,0 +1,1019 @@
+use chrono::{Duration, FixedOffset, NaiveDate, NaiveDateTime, NaiveTime, TimeZone, Utc};
+use flexi_logger::{writers::LogWriter, DeferredNow, Record};
+use flexi_logger_rotate_writer::{
+    policy::{self, RotationPolicy},
+    Compression, MockClock, NamingScheme, OverflowPolicy, RotateLogWriter, RotationEvent,
+    RotationTimezone, WriteMode,
+};
+use std::{
+    path::{Path, PathBuf},
+    sync::{mpsc, Arc, Mutex},
+    time::Duration as StdDuration,
+};
+
+fn write_line(writer: &RotateLogWriter, line: &str) {
+    writer
+        .write(
+            &mut DeferredNow::new(),
+            &Record::builder().args(format_args!("{}", line)).build(),
+        )
+        .unwrap();
+}
+
+fn read_log(dir: &Path, file_name: &str) -> String {
+    std::fs::read_to_string(dir.join(file_name)).unwrap()
+}
+
+fn log_file_names(dir: &Path) -> Vec<String> {
+    let mut names: Vec<String> = std::fs::read_dir(dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+        .collect();
+    names.sort();
+    names
+}
+
+fn build_writer(dir: &Path, clock: &MockClock) -> RotateLogWriter {
+    RotateLogWriter::builder()
+        .directory(dir)
+        .basename("foo")
+        .timezone(RotationTimezone::Utc)
+        .clock(clock.clone())
+        .try_build()
+        .unwrap()
+}
+
+#[test]
+fn rotates_at_midnight() {
+    let dir = tempfile::tempdir().unwrap();
+    let clock = MockClock::new(Utc.with_ymd_and_hms(2021, 3, 28, 23, 59, 59).unwrap());
+    let writer = build_writer(dir.path(), &clock);
+
+    write_line(&writer, "before midnight");
+    clock.advance(Duration::seconds(1));
+    write_line(&writer, "after midnight");
+
+    assert_eq!(
+        log_file_names(dir.path()),
+        ["foo_r2021-03-28.log", "foo_r2021-03-29.log"]
+    );
+    assert!(read_log(dir.path(), "foo_r2021-03-28.log").contains("before midnight"));
+    assert!(read_log(dir.path(), "foo_r2021-03-29.log").contains("after midnight"));
+}
+
+#[test]
+fn does_not_go_back_when_clock_is_set_back() {
+    let dir = tempfile::tempdir().unwrap();
+    let clock = MockClock::new(Utc.with_ymd_and_hms(2021, 3, 29, 0, 0, 10).unwrap());
+    let writer = build_writer(dir.path(), &clock);
+
+    write_line(&writer, "first");
+    // e.g. NTP corrects the clock across midnight
+    clock.set(Utc.with_ymd_and_hms(2021, 3, 28, 23, 59, 50).unwrap());
+    write_line(&writer, "after the clock was set back");
+
+    assert_eq!(log_file_names(dir.path()), ["foo_r2021-03-29.log"]);
+    let log = read_log(dir.path(), "foo_r2021-03-29.log");
+    assert!(log.contains("first"));
+    assert!(log.contains("after the clock was set back"));
+}
+
+#[test]
+fn rotates_again_when_time_catches_up() {
+    let dir = tempfile::tempdir().unwrap();
+    let clock = MockClock::new(Utc.with_ymd_and_hms(2021, 3, 29, 12, 0, 0).unwrap());
+    let writer = build_writer(dir.path(), &clock);
+
+    write_line(&writer, "first");
+    clock.advance(Duration::days(-2));
+    write_line(&writer, "in the past");
+    clock.set(Utc.with_ymd_and_hms(2021, 3, 29, 23, 0, 0).unwrap());
+    write_line(&writer, "same day again");
+    clock.advance(Duration::hours(2));
+    write_line(&writer, "next day");
+
+    assert_eq!(
+        log_file_names(dir.path()),
+        ["foo_r2021-03-29.log", "foo_r2021-03-30.log"]
+    );
+    let log = read_log(dir.path(), "foo_r2021-03-29.log");
+    assert!(log.contains("in the past"));
+    assert!(log.contains("same day again"));
+    assert!(read_log(dir.path(), "foo_r2021-03-30.log").contains("next day"));
+}
+
+#[test]
+fn size_rotation_stays_in_current_period_when_clock_is_set_back() {
+    let dir = tempfile::tempdir().unwrap();
+    let clock = MockClock::new(Utc.with_ymd_and_hms(2021, 3, 29, 0, 0, 10).unwrap());
+    let writer = RotateLogWriter::builder()
+        .directory(dir.path())
+        .basename("foo")
+        .timezone(RotationTimezone::Utc)
+        .clock(clock.clone())
+        .max_file_size(1)
+        .try_build()
+        .unwrap();
+
+    write_line(&writer, "first");
+    clock.advance(Duration::minutes(-1));
+    write_line(&writer, "second");
+
+    assert_eq!(
+        log_file_names(dir.path()),
+        ["foo_r2021-03-29.1.log", "foo_r2021-03-29.log"]
+    );
+}
+
+fn touch(dir: &Path, file_name: &str, content: &str) {
+    std::fs::write(dir.join(file_name), content).unwrap();
+}
+
+// writes one line per day, and leaves the clock at the last line
+fn write_daily_lines(writer: &RotateLogWriter, clock: &MockClock, days: usize) {
+    for day in 0..days {
+        if day > 0 {
+            clock.advance(Duration::days(1));
+        }
+        write_line(writer, &format!("day {}", day));
+    }
+}
+
+#[test]
+fn keep_files_removes_the_oldest_files() {
+    let dir = tempfile::tempdir().unwrap();
+    let clock = MockClock::new(Utc.with_ymd_and_hms(2021, 3, 28, 12, 0, 0).unwrap());
+    let writer = RotateLogWriter::builder()
+        .directory(dir.path())
+        .basename("foo")
+        .timezone(RotationTimezone::Utc)
+        .clock(clock.clone())
+        .keep_files(2)
+        .try_build()
+        .unwrap();
+
+    write_daily_lines(&writer, &clock, 4);
+    writer.shutdown();
+
+    assert_eq!(
+        log_file_names(dir.path()),
+        ["foo_r2021-03-30.log", "foo_r2021-03-31.log"]
+    );
+}
+
+#[test]
+fn cleanup_keeps_unrelated_files() {
+    let dir = tempfile::tempdir().unwrap();
+    touch(dir.path(), "foo_r2021-03-20.log", "old\n");
+    touch(
+        dir.path(),
+        "foo_bar_r2021-03-20.log",
+        "other discriminant\n",
+    );
+    touch(dir.path(), "foo_r2021-03-20.txt", "other suffix\n");
+    touch(dir.path(), "unrelated.log", "unrelated\n");
+    let clock = MockClock::new(Utc.with_ymd_and_hms(2021, 3, 28, 12, 0, 0).unwrap());
+    let writer = RotateLogWriter::builder()
+        .directory(dir.path())
+        .basename("foo")
+        .timezone(RotationTimezone::Utc)
+        .clock(clock.clone())
+        .keep_files(1)
+        .try_build()
+        .unwrap();
+
+    write_daily_lines(&writer, &clock, 2);
+    writer.shutdown();
+
+    assert_eq!(
+        log_file_names(dir.path()),
+        [
+            "foo_bar_r2021-03-20.log",
+            "foo_r2021-03-20.txt",
+            "foo_r2021-03-29.log",
+            "unrelated.log"
+        ]
+    );
+}
+
+#[test]
+fn cleanup_keeps_files_of_other_discriminants() {
+    let dir = tempfile::tempdir().unwrap();
+    touch(dir.path(), "foo_r2021-03-20.log", "no discriminant\n");
+    touch(
+        dir.path(),
+        "foo_baz_r2021-03-20.log",
+        "other discriminant\n",
+    );
+    let clock = MockClock::new(Utc.with_ymd_and_hms(2021, 3, 28, 12, 0, 0).unwrap());
+    let writer = RotateLogWriter::builder()
+        .directory(dir.path())
+        .basename("foo")
+        .discriminant("bar")
+        .timezone(RotationTimezone::Utc)
+        .clock(clock.clone())
+        .keep_files(1)
+        .try_build()
+        .unwrap();
+
+    write_daily_lines(&writer, &clock, 3);
+    writer.shutdown();
+
+    assert_eq!(
+        log_file_names(dir.path()),
+        [
+            "foo_bar_r2021-03-30.log",
+            "foo_baz_r2021-03-20.log",
+            "foo_r2021-03-20.log"
+        ]
+    );
+}
+
+#[test]
+fn max_age_removes_old_files_at_startup() {
+    let dir = tempfile::tempdir().unwrap();
+    touch(dir.path(), "foo_r2021-03-20.log", "too old\n");
+    touch(dir.path(), "foo_r2021-03-25.log", "too old\n");
+    touch(dir.path(), "foo_r2021-03-26.log", "young enough\n");
+    let clock = MockClock::new(Utc.with_ymd_and_hms(2021, 3, 28, 12, 0, 0).unwrap());
+    let _writer = RotateLogWriter::builder()
+        .directory(dir.path())
+        .basename("foo")
+        .timezone(RotationTimezone::Utc)
+        .clock(clock.clone())
+        .max_age(std::time::Duration::from_secs(2 * 24 * 3600))
+        .try_build()
+        .unwrap();
+
+    // the file of 03-26 ends at 03-27 00:00, which is less than two days ago
+    assert_eq!(log_file_names(dir.path()), ["foo_r2021-03-26.log"]);
+}
+
+#[test]
+fn max_age_removes_old_files_on_rotation() {
+    let dir = tempfile::tempdir().unwrap();
+    let clock = MockClock::new(Utc.with_ymd_and_hms(2021, 3, 28, 12, 0, 0).unwrap());
+    let writer = RotateLogWriter::builder()
+        .directory(dir.path())
+        .basename("foo")
+        .timezone(RotationTimezone::Utc)
+        .clock(clock.clone())
+        .max_age(std::time::Duration::from_secs(36 * 3600))
+        .try_build()
+        .unwrap();
+
+    // the last line is written on 03-31 12:00, when the file of 03-29 is 36 hours old
+    write_daily_lines(&writer, &clock, 4);
+    writer.shutdown();
+
+    assert_eq!(
+        log_file_names(dir.path()),
+        ["foo_r2021-03-30.log", "foo_r2021-03-31.log"]
+    );
+}
+
+#[test]
+fn max_total_size_removes_the_oldest_files() {
+    let dir = tempfile::tempdir().unwrap();
+    let clock = MockClock::new(Utc.with_ymd_and_hms(2021, 3, 28, 12, 0, 0).unwrap());
+    let writer = RotateLogWriter::builder()
+        .directory(dir.path())
+        .basename("foo")
+        .timezone(RotationTimezone::Utc)
+        .clock(clock.clone())
+        .format(|w, _now, record| write!(w, "{}", record.args()))
+        .max_total_size(12)
+        .try_build()
+        .unwrap();
+
+    // each file has 6 bytes: "day N\n"
+    write_daily_lines(&writer, &clock, 4);
+    writer.shutdown();
+
+    assert_eq!(
+        log_file_names(dir.path()),
+        ["foo_r2021-03-30.log", "foo_r2021-03-31.log"]
+    );
+}
+
+#[test]
+fn max_total_size_keeps_the_continued_file_at_startup() {
+    let dir = tempfile::tempdir().unwrap();
+    touch(dir.path(), "foo_r2021-03-27.log", &"x".repeat(100));
+    touch(dir.path(), "foo_r2021-03-28.log", &"x".repeat(100));
+    let clock = MockClock::new(Utc.with_ymd_and_hms(2021, 3, 28, 12, 0, 0).unwrap());
+    let writer = RotateLogWriter::builder()
+        .directory(dir.path())
+        .basename("foo")
+        .timezone(RotationTimezone::Utc)
+        .clock(clock.clone())
+        .max_total_size(10)
+        .try_build()
+        .unwrap();
+
+    write_line(&writer, "appended");
+    writer.shutdown();
+
+    assert_eq!(log_file_names(dir.path()), ["foo_r2021-03-28.log"]);
+    let log = read_log(dir.path(), "foo_r2021-03-28.log");
+    assert!(log.starts_with(&"x".repeat(100)));
+    assert!(log.contains("appended"));
+}
+
+fn build_current_file_writer(dir: &Path, clock: &MockClock) -> RotateLogWriter {
+    RotateLogWriter::builder()
+        .directory(dir)
+        .basename("foo")
+        .timezone(RotationTimezone::Utc)
+        .clock(clock.clone())
+        .use_current_file(true)
+        .try_build()
+        .unwrap()
+}
+
+#[test]
+fn current_file_is_renamed_on_restart_in_a_later_period() {
+    let dir = tempfile::tempdir().unwrap();
+    let clock = MockClock::new(Utc.with_ymd_and_hms(2021, 3, 29, 12, 0, 0).unwrap());
+
+    let writer = build_current_file_writer(dir.path(), &clock);
+    write_line(&writer, "first run");
+    writer.shutdown();
+    drop(writer);
+
+    clock.advance(Duration::days(1));
+    let writer = build_current_file_writer(dir.path(), &clock);
+    write_line(&writer, "second run");
+    writer.shutdown();
+
+    assert_eq!(
+        log_file_names(dir.path()),
+        ["foo.log", "foo_r2021-03-29.log"]
+    );
+    assert!(read_log(dir.path(), "foo_r2021-03-29.log").contains("first run"));
+    let log = read_log(dir.path(), "foo.log");
+    assert!(log.contains("second run"));
+    assert!(!log.contains("first run"));
+}
+
+#[test]
+fn current_file_is_continued_on_restart_in_the_same_period() {
+    let dir = tempfile::tempdir().unwrap();
+    let clock = MockClock::new(Utc.with_ymd_and_hms(2021, 3, 29, 12, 0, 0).unwrap());
+
+    let writer = build_current_file_writer(dir.path(), &clock);
+    write_line(&writer, "first run");
+    writer.shutdown();
+    drop(writer);
+
+    clock.advance(Duration::hours(1));
+    let writer = build_current_file_writer(dir.path(), &clock);
+    write_line(&writer, "second run");
+    writer.shutdown();
+
+    assert_eq!(log_file_names(dir.path()), ["foo.log"]);
+    let log = read_log(dir.path(), "foo.log");
+    assert!(log.contains("first run"));
+    assert!(log.contains("second run"));
+}
+
+#[test]
+fn size_policy_skips_full_files_on_restart() {
+    let dir = tempfile::tempdir().unwrap();
+    touch(dir.path(), "foo_r2021-03-28.log", &"x".repeat(60));
+    touch(dir.path(), "foo_r2021-03-28.1.log", &"x".repeat(60));
+    let clock = MockClock::new(Utc.with_ymd_and_hms(2021, 3, 28, 12, 0, 0).unwrap());
+    let writer = RotateLogWriter::builder()
+        .directory(dir.path())
+        .basename("foo")
+        .timezone(RotationTimezone::Utc)
+        .clock(clock.clone())
+        .rotation_policy(policy::any_of(vec![policy::daily(), policy::max_size(60)]))
+        .try_build()
+        .unwrap();
+
+    write_line(&writer, "after restart");
+    writer.shutdown();
+
+    assert_eq!(
+        log_file_names(dir.path()),
+        [
+            "foo_r2021-03-28.1.log",
+            "foo_r2021-03-28.2.log",
+            "foo_r2021-03-28.log"
+        ]
+    );
+    assert_eq!(read_log(dir.path(), "foo_r2021-03-28.log").len(), 60);
+    assert_eq!(read_log(dir.path(), "foo_r2021-03-28.1.log").len(), 60);
+    assert!(read_log(dir.path(), "foo_r2021-03-28.2.log").contains("after restart"));
+}
+
+#[test]
+fn panicking_on_rotate_callback_does_not_stop_the_housekeeping() {
+    let dir = tempfile::tempdir().unwrap();
+    let clock = MockClock::new(Utc.with_ymd_and_hms(2021, 3, 28, 12, 0, 0).unwrap());
+    let events = Arc::new(Mutex::new(Vec::new()));
+    let callback_events = Arc::clone(&events);
+    let writer = RotateLogWriter::builder()
+        .directory(dir.path())
+        .basename("foo")
+        .timezone(RotationTimezone::Utc)
+        .clock(clock.clone())
+        .on_rotate(Box::new(move |event: RotationEvent| {
+            let count = {
+                let mut events = callback_events.lock().unwrap();
+                events.push(event.closed_path);
+                events.len()
+            };
+            assert!(count > 1, "first rotation");
+        }))
+        .try_build()
+        .unwrap();
+
+    write_daily_lines(&writer, &clock, 3);
+    writer.shutdown();
+
+    assert_eq!(
+        *events.lock().unwrap(),
+        [
+            dir.path().join("foo_r2021-03-28.log"),
+            dir.path().join("foo_r2021-03-29.log")
+        ]
+    );
+}
+
+#[test]
+fn period_bounds_include_the_rotation_time() {
+    let dir = tempfile::tempdir().unwrap();
+    let clock = MockClock::new(Utc.with_ymd_and_hms(2021, 3, 29, 3, 0, 0).unwrap());
+    let events = Arc::new(Mutex::new(Vec::new()));
+    let callback_events = Arc::clone(&events);
+    let writer = RotateLogWriter::builder()
+        .directory(dir.path())
+        .basename("foo")
+        .timezone(RotationTimezone::Utc)
+        .rotate_at(NaiveTime::from_hms_opt(4, 0, 0).unwrap())
+        .clock(clock.clone())
+        .on_rotate(Box::new(move |event: RotationEvent| {
+            callback_events.lock().unwrap().push(event);
+        }))
+        .try_build()
+        .unwrap();
+
+    write_line(&writer, "before 04:00");
+    clock.advance(Duration::hours(1));
+    write_line(&writer, "after 04:00");
+    let log_files = writer.log_files().unwrap();
+    writer.shutdown();
+
+    let events = events.lock().unwrap();
+    assert_eq!(events.len(), 1);
+    assert_eq!(
+        events[0].closed_path,
+        dir.path().join("foo_r2021-03-28.log")
+    );
+    assert_eq!(
+        events[0].period_start,
+        NaiveDate::from_ymd_opt(2021, 3, 28)
+            .unwrap()
+            .and_hms_opt(4, 0, 0)
+            .unwrap()
+    );
+    assert_eq!(
+        events[0].period_end,
+        NaiveDate::from_ymd_opt(2021, 3, 29)
+            .unwrap()
+            .and_hms_opt(4, 0, 0)
+            .unwrap()
+    );
+    assert_eq!(
+        log_files
+            .iter()
+            .map(|log_file| log_file.period_start)
+            .collect::<Vec<_>>(),
+        [events[0].period_start, events[0].period_end]
+    );
+}
+
+#[test]
+fn rotation_policy_requires_seq_in_the_template() {
+    let dir = tempfile::tempdir().unwrap();
+    let result = RotateLogWriter::builder()
+        .directory(dir.path())
+        .basename("foo")
+        .filename_template("{basename}-{date}.log")
+        .rotation_policy(policy::max_lines(2))
+        .try_build();
+
+    assert!(result.is_err());
+}
+
+#[test]
+fn rotation_policy_with_seq_keeps_all_records() {
+    let dir = tempfile::tempdir().unwrap();
+    let clock = MockClock::new(Utc.with_ymd_and_hms(2021, 3, 28, 12, 0, 0).unwrap());
+    let writer = RotateLogWriter::builder()
+        .directory(dir.path())
+        .basename("foo")
+        .filename_template("{basename}-{date}.{seq}.log")
+        .timezone(RotationTimezone::Utc)
+        .clock(clock.clone())
+        .rotation_policy(policy::max_lines(2))
+        .try_build()
+        .unwrap();
+
+    for i in 0..7 {
+        write_line(&writer, &format!("line {}", i));
+    }
+    writer.shutdown();
+
+    assert_eq!(
+        log_file_names(dir.path()),
+        [
+            "foo-2021-03-28.0.log",
+            "foo-2021-03-28.1.log",
+            "foo-2021-03-28.2.log",
+            "foo-2021-03-28.3.log"
+        ]
+    );
+    assert!(read_log(dir.path(), "foo-2021-03-28.3.log").ends_with("line 6\n"));
+    assert_eq!(writer.dropped_records().total(), 0);
+}
+
+// a naming scheme that cannot tell the files of a period apart
+struct DateOnly;
+
+impl NamingScheme for DateOnly {
+    fn file_path(&self, period_start: NaiveDateTime, _sequence: u32) -> PathBuf {
+        PathBuf::from(format!("foo-{}.log", period_start.format("%Y-%m-%d")))
+    }
+
+    fn parse_path(&self, path: &Path) -> Option<(NaiveDateTime, u32)> {
+        let date = path.to_str()?.strip_prefix("foo-")?.strip_suffix(".log")?;
+        let period_start = NaiveDate::parse_from_str(date, "%Y-%m-%d")
+            .ok()?
+            .and_hms_opt(0, 0, 0)?;
+        Some((period_start, 0))
+    }
+}
+
+#[test]
+fn naming_without_sequence_continues_the_current_file() {
+    let dir = tempfile::tempdir().unwrap();
+    let clock = MockClock::new(Utc.with_ymd_and_hms(2021, 3, 28, 12, 0, 0).unwrap());
+    let events = Arc::new(Mutex::new(Vec::new()));
+    let callback_events = Arc::clone(&events);
+    let writer = RotateLogWriter::builder()
+        .directory(dir.path())
+        .timezone(RotationTimezone::Utc)
+        .clock(clock.clone())
+        .naming_scheme(DateOnly)
+        .max_file_size(10)
+        .compress_rotated(Compression::Gzip)
+        .on_rotate(Box::new(move |event: RotationEvent| {
+            callback_events.lock().unwrap().push(event);
+        }))
+        .try_build()
+        .unwrap();
+
+    for i in 0..7 {
+        write_line(&writer, &format!("line {}", i));
+    }
+    writer.shutdown();
+
+    assert!(events.lock().unwrap().is_empty());
+    assert_eq!(log_file_names(dir.path()), ["foo-2021-03-28.log"]);
+    let log = read_log(dir.path(), "foo-2021-03-28.log");
+    for i in 0..7 {
+        assert!(log.contains(&format!("line {}", i)));
+    }
+    assert_eq!(writer.dropped_records().total(), 0);
+}
+
+#[test]
+fn blocking_async_writer_does_not_hang_after_shutdown() {
+    let dir = tempfile::tempdir().unwrap();
+    let clock = MockClock::new(Utc.with_ymd_and_hms(2021, 3, 28, 12, 0, 0).unwrap());
+    let writer = Arc::new(
+        RotateLogWriter::builder()
+            .directory(dir.path())
+            .basename("foo")
+            .timezone(RotationTimezone::Utc)
+            .clock(clock)
+            .write_mode(WriteMode::Async {
+                capacity: 2,
+                overflow: OverflowPolicy::Block,
+            })
+            .try_build()
+            .unwrap(),
+    );
+    write_line(&writer, "before shutdown");
+    writer.shutdown();
+
+    let (done_sender, done_receiver) = mpsc::channel();
+    let thread_writer = Arc::clone(&writer);
+    std::thread::spawn(move || {
+        for i in 0..5 {
+            write_line(&thread_writer, &format!("after shutdown {}", i));
+        }
+        done_sender.send(()).unwrap();
+    });
+
+    done_receiver
+        .recv_timeout(StdDuration::from_secs(5))
+        .expect("logging after the shutdown blocked");
+    assert_eq!(writer.dropped_records().io_error, 5);
+    assert!(!read_log(dir.path(), "foo_r2021-03-28.log").contains("after shutdown"));
+}
+
+// a policy that never rotates, but lets a test stall the writer thread
+struct Gate {
+    lock: Arc<Mutex<()>>,
+    entered: mpsc::Sender<()>,
+}
+
+impl RotationPolicy for Gate {
+    fn time_passed(&mut self, _now: NaiveDateTime) {
+        self.entered.send(()).ok();
+        drop(self.lock.lock().unwrap());
+    }
+
+    fn rotation_necessary(&self) -> bool {
+        false
+    }
+}
+
+// builds an async writer with a capacity of 2, whose writer thread waits while `lock` is held,
+// and notifies `entered` when it gets there
+fn build_gated_async_writer(
+    dir: &Path,
+    overflow: OverflowPolicy,
+    lock: &Arc<Mutex<()>>,
+) -> (RotateLogWriter, mpsc::Receiver<()>) {
+    let (entered_sender, entered_receiver) = mpsc::channel();
+    let writer = RotateLogWriter::builder()
+        .directory(dir)
+        .basename("foo")
+        .timezone(RotationTimezone::Utc)
+        .clock(MockClock::new(
+            Utc.with_ymd_and_hms(2021, 3, 28, 12, 0, 0).unwrap(),
+        ))
+        .write_mode(WriteMode::Async {
+            capacity: 2,
+            overflow,
+        })
+        .rotation_policy(Box::new(Gate {
+            lock: Arc::clone(lock),
+            entered: entered_sender,
+        }))
+        .try_build()
+        .unwrap();
+    (writer, entered_receiver)
+}
+
+#[test]
+fn async_drop_newest_drops_the_new_lines() {
+    let dir = tempfile::tempdir().unwrap();
+    let lock = Arc::new(Mutex::new(()));
+    let guard = lock.lock().unwrap();
+    let (writer, entered) = build_gated_async_writer(dir.path(), OverflowPolicy::DropNewest, &lock);
+
+    write_line(&writer, "line 0");
+    // the writer thread holds line 0, and the channel is empty
+    entered.recv().unwrap();
+    for i in 1..5 {
+        write_line(&writer, &format!("line {}", i));
+    }
+    drop(guard);
+    writer.shutdown();
+
+    let log = read_log(dir.path(), "foo_r2021-03-28.log");
+    for i in 0..3 {
+        assert!(log.contains(&format!("line {}", i)));
+    }
+    assert!(!log.contains("line 3"));
+    assert!(!log.contains("line 4"));
+    assert!(log.contains("[flexi_logger] 2 records dropped between"));
+    assert_eq!(writer.dropped_records().overflow, 2);
+}
+
+#[test]
+fn async_drop_oldest_drops_the_old_lines() {
+    let dir = tempfile::tempdir().unwrap();
+    let lock = Arc::new(Mutex::new(()));
+    let guard = lock.lock().unwrap();
+    let (writer, entered) = build_gated_async_writer(dir.path(), OverflowPolicy::DropOldest, &lock);
+
+    write_line(&writer, "line 0");
+    entered.recv().unwrap();
+    for i in 1..5 {
+        write_line(&writer, &format!("line {}", i));
+    }
+    drop(guard);
+    writer.shutdown();
+
+    let log = read_log(dir.path(), "foo_r2021-03-28.log");
+    assert!(!log.contains("line 1"));
+    assert!(!log.contains("line 2"));
+    for i in [0, 3, 4] {
+        assert!(log.contains(&format!("line {}", i)));
+    }
+    assert_eq!(writer.dropped_records().overflow, 2);
+}
+
+#[test]
+fn async_block_waits_for_space() {
+    let dir = tempfile::tempdir().unwrap();
+    let lock = Arc::new(Mutex::new(()));
+    let guard = lock.lock().unwrap();
+    let (writer, entered) = build_gated_async_writer(dir.path(), OverflowPolicy::Block, &lock);
+    let writer = Arc::new(writer);
+
+    write_line(&writer, "line 0");
+    entered.recv().unwrap();
+    let (done_sender, done_receiver) = mpsc::channel();
+    let thread_writer = Arc::clone(&writer);
+    std::thread::spawn(move || {
+        for i in 1..5 {
+            write_line(&thread_writer, &format!("line {}", i));
+        }
+        done_sender.send(()).unwrap();
+    });
+    assert!(done_receiver
+        .recv_timeout(StdDuration::from_millis(200))
+        .is_err());
+    drop(guard);
+    done_receiver.recv().unwrap();
+    writer.shutdown();
+
+    let log = read_log(dir.path(), "foo_r2021-03-28.log");
+    for i in 0..5 {
+        assert!(log.contains(&format!("line {}", i)));
+    }
+    assert_eq!(writer.dropped_records().total(), 0);
+}
+
+fn build_async_writer(dir: &Path) -> RotateLogWriter {
+    RotateLogWriter::builder()
+        .directory(dir)
+        .basename("foo")
+        .timezone(RotationTimezone::Utc)
+        .clock(MockClock::new(
+            Utc.with_ymd_and_hms(2021, 3, 28, 12, 0, 0).unwrap(),
+        ))
+        .write_mode(WriteMode::Async {
+            capacity: 100,
+            overflow: OverflowPolicy::Block,
+        })
+        .buffer_with_capacity(64 * 1024)
+        .try_build()
+        .unwrap()
+}
+
+#[test]
+fn async_flush_writes_the_lines_logged_before() {
+    let dir = tempfile::tempdir().unwrap();
+    let writer = build_async_writer(dir.path());
+
+    for i in 0..50 {
+        write_line(&writer, &format!("line {}", i));
+    }
+    writer.flush().unwrap();
+
+    let log = read_log(dir.path(), "foo_r2021-03-28.log");
+    assert_eq!(log.lines().count(), 50);
+    assert!(log.ends_with("line 49\n"));
+}
+
+#[test]
+fn async_shutdown_waits_for_the_writer_thread() {
+    let dir = tempfile::tempdir().unwrap();
+    let writer = build_async_writer(dir.path());
+
+    for i in 0..50 {
+        write_line(&writer, &format!("line {}", i));
+    }
+    writer.shutdown();
+
+    let log = read_log(dir.path(), "foo_r2021-03-28.log");
+    assert_eq!(log.lines().count(), 50);
+    assert!(log.ends_with("line 49\n"));
+}
+
+fn build_writer_in_timezone(
+    dir: &Path,
+    clock: &MockClock,
+    timezone: RotationTimezone,
+) -> RotateLogWriter {
+    RotateLogWriter::builder()
+        .directory(dir)
+        .basename("foo")
+        .timezone(timezone)
+        .clock(clock.clone())
+        .try_build()
+        .unwrap()
+}
+
+#[test]
+fn fixed_offset_rotates_at_local_midnight() {
+    let dir = tempfile::tempdir().unwrap();
+    // 23:59:59 at UTC+02:00
+    let clock = MockClock::new(Utc.with_ymd_and_hms(2021, 3, 28, 21, 59, 59).unwrap());
+    let offset = FixedOffset::east_opt(2 * 3600).unwrap();
+    let writer = build_writer_in_timezone(dir.path(), &clock, RotationTimezone::Fixed(offset));
+
+    write_line(&writer, "before midnight");
+    clock.advance(Duration::seconds(1));
+    write_line(&writer, "after midnight");
+    writer.shutdown();
+
+    assert_eq!(
+        log_file_names(dir.path()),
+        ["foo_r2021-03-28.log", "foo_r2021-03-29.log"]
+    );
+    assert!(read_log(dir.path(), "foo_r2021-03-28.log").contains("before midnight"));
+    assert!(read_log(dir.path(), "foo_r2021-03-29.log").contains("after midnight"));
+}
+
+#[test]
+fn utc_ignores_the_local_timezone() {
+    let dir = tempfile::tempdir().unwrap();
+    let clock = MockClock::new(Utc.with_ymd_and_hms(2021, 3, 28, 21, 59, 59).unwrap());
+    let writer = build_writer_in_timezone(dir.path(), &clock, RotationTimezone::Utc);
+
+    write_line(&writer, "evening");
+    clock.advance(Duration::hours(2));
+    write_line(&writer, "before midnight");
+    writer.shutdown();
+
+    assert_eq!(log_file_names(dir.path()), ["foo_r2021-03-28.log"]);
+}
+
+#[cfg(feature = "chrono-tz")]
+#[test]
+fn named_timezone_rotates_at_local_midnight_after_dst_change() {
+    let dir = tempfile::tempdir().unwrap();
+    // Europe/Berlin switched to UTC+02:00 on 2021-03-28 at 01:00 UTC
+    let clock = MockClock::new(Utc.with_ymd_and_hms(2021, 3, 28, 21, 59, 59).unwrap());
+    let writer = build_writer_in_timezone(
+        dir.path(),
+        &clock,
+        RotationTimezone::Named(chrono_tz::Europe::Berlin),
+    );
+
+    write_line(&writer, "before midnight");
+    clock.advance(Duration::seconds(1));
+    write_line(&writer, "after midnight");
+    writer.shutdown();
+
+    assert_eq!(
+        log_file_names(dir.path()),
+        ["foo_r2021-03-28.log", "foo_r2021-03-29.log"]
+    );
+}
+
+// waits until the file appears, since the timer checks the mock clock only once per second
+fn wait_for_file(dir: &Path, file_name: &str) -> bool {
+    let deadline = std::time::Instant::now() + StdDuration::from_secs(5);
+    while std::time::Instant::now() < deadline {
+        if dir.join(file_name).exists() {
+            return true;
+        }
+        std::thread::sleep(StdDuration::from_millis(10));
+    }
+    false
+}
+
+fn check_timer_rotates_without_writes(write_mode: WriteMode) {
+    let dir = tempfile::tempdir().unwrap();
+    let clock = MockClock::new(Utc.with_ymd_and_hms(2021, 3, 28, 12, 0, 0).unwrap());
+    let writer = RotateLogWriter::builder()
+        .directory(dir.path())
+        .basename("foo")
+        .timezone(RotationTimezone::Utc)
+        .clock(clock.clone())
+        .rotate_on_timer(true)
+        .write_mode(write_mode)
+        .try_build()
+        .unwrap();
+
+    write_line(&writer, "first day");
+    writer.flush().unwrap();
+    clock.advance(Duration::days(1));
+
+    assert!(wait_for_file(dir.path(), "foo_r2021-03-29.log"));
+    writer.shutdown();
+    assert_eq!(
+        log_file_names(dir.path()),
+        ["foo_r2021-03-28.log", "foo_r2021-03-29.log"]
+    );
+    assert!(read_log(dir.path(), "foo_r2021-03-29.log").is_empty());
+}
+
+#[test]
+fn timer_rotates_without_writes_in_direct_mode() {
+    check_timer_rotates_without_writes(WriteMode::Direct);
+}
+
+#[test]
+fn timer_rotates_without_writes_in_async_mode() {
+    check_timer_rotates_without_writes(WriteMode::Async {
+        capacity: 10,
+        overflow: OverflowPolicy::Block,
+    });
+}
+
+#[test]
+fn new_file_on_start_starts_a_numbered_file_per_start() {
+    let dir = tempfile::tempdir().unwrap();
+    let clock = MockClock::new(Utc.with_ymd_and_hms(2021, 3, 28, 12, 0, 0).unwrap());
+    for start in 0..3 {
+        let writer = RotateLogWriter::builder()
+            .directory(dir.path())
+            .basename("foo")
+            .timezone(RotationTimezone::Utc)
+            .clock(clock.clone())
+            .new_file_on_start(true)
+            .try_build()
+            .unwrap();
+        write_line(&writer, &format!("start {}", start));
+        writer.shutdown();
+    }
+
+    assert_eq!(
+        log_file_names(dir.path()),
+        [
+            "foo_r2021-03-28.1.log",
+            "foo_r2021-03-28.2.log",
+            "foo_r2021-03-28.log"
+        ]
+    );
+    assert!(read_log(dir.path(), "foo_r2021-03-28.log").contains("start 0"));
+    assert!(read_log(dir.path(), "foo_r2021-03-28.1.log").contains("start 1"));
+    assert!(read_log(dir.path(), "foo_r2021-03-28.2.log").contains("start 2"));
+}
+
+#[test]
+fn new_file_on_start_requires_seq_in_the_template() {
+    let dir = tempfile::tempdir().unwrap();
+    let result = RotateLogWriter::builder()
+        .directory(dir.path())
+        .basename("foo")
+        .filename_template("{basename}-{date}.log")
+        .new_file_on_start(true)
+        .try_build();
+
+    assert!(result.is_err());
+}
+
+#[test]
+fn max_file_size_starts_numbered_files() {
+    let dir = tempfile::tempdir().unwrap();
+    let clock = MockClock::new(Utc.with_ymd_and_hms(2021, 3, 28, 12, 0, 0).unwrap());
+    let writer = RotateLogWriter::builder()
+        .directory(dir.path())
+        .basename("foo")
+        .timezone(RotationTimezone::Utc)
+        .clock(clock.clone())
+        .max_file_size(40)
+        .try_build()
+        .unwrap();
+
+    // each line has 24 bytes, so each file takes two lines
+    for i in 0..5 {
+        write_line(&writer, &format!("line {}", i));
+    }
+    writer.shutdown();
+
+    assert_eq!(
+        log_file_names(dir.path()),
+        [
+            "foo_r2021-03-28.1.log",
+            "foo_r2021-03-28.2.log",
+            "foo_r2021-03-28.log"
+        ]
+    );
+    assert_eq!(
+        read_log(dir.path(), "foo_r2021-03-28.log").lines().count(),
+        2
+    );
+    assert_eq!(
+        read_log(dir.path(), "foo_r2021-03-28.1.log")
+            .lines()
+            .count(),
+        2
+    );
+    assert!(read_log(dir.path(), "foo_r2021-03-28.2.log").ends_with("line 4\n"));
+}
+
+#[test]
+fn keep_files_must_keep_the_current_file() {
+    let dir = tempfile::tempdir().unwrap();
+    let result = RotateLogWriter::builder()
+        .directory(dir.path())
+        .basename("foo")
+        .keep_files(0)
+        .try_build();
+
+    assert!(result.is_err());
+}
@@ -0,0 +1,144 @@
+use chrono::{Duration, TimeZone, Utc};
+use flexi_logger::{writers::LogWriter, DeferredNow, Record};
+use flexi_logger_rotate_writer::{
+    MockClock, RotateLogWriter, RotateLogWriterBuilder, RotationTimezone,
+};
+use std::path::{Path, PathBuf};
+
+fn write_line(writer: &RotateLogWriter, line: &str) {
+    writer
+        .write(
+            &mut DeferredNow::new(),
+            &Record::builder().args(format_args!("{}", line)).build(),
+        )
+        .unwrap();
+}
+
+// the paths of all files below `dir`, relative to it, with '/' as separator
+fn relative_file_paths(dir: &Path) -> Vec<String> {
+    fn collect(dir: &Path, prefix: &str, paths: &mut Vec<String>) {
+        for entry in std::fs::read_dir(dir).unwrap() {
+            let entry = entry.unwrap();
+            let name = format!("{}{}", prefix, entry.file_name().to_string_lossy());
+            if entry.file_type().unwrap().is_dir() {
+                collect(&entry.path(), &format!("{}/", name), paths);
+            } else {
+                paths.push(name);
+            }
+        }
+    }
+    let mut paths = Vec::new();
+    collect(dir, "", &mut paths);
+    paths.sort();
+    paths
+}
+
+fn builder(dir: &Path, clock: &MockClock, subdirectory: &str) -> RotateLogWriterBuilder {
+    RotateLogWriter::builder()
+        .directory(dir)
+        .basename("foo")
+        .timezone(RotationTimezone::Utc)
+        .clock(clock.clone())
+        .subdirectory(subdirectory)
+}
+
+fn write_daily_lines(writer: &RotateLogWriter, clock: &MockClock, days: usize) {
+    for day in 0..days {
+        if day > 0 {
+            clock.advance(Duration::days(1));
+        }
+        write_line(writer, &format!("day {}", day));
+    }
+}
+
+#[test]
+fn log_files_are_nested_in_dated_subdirectories() {
+    let dir = tempfile::tempdir().unwrap();
+    let clock = MockClock::new(Utc.with_ymd_and_hms(2021, 3, 31, 12, 0, 0).unwrap());
+    let writer = builder(dir.path(), &clock, "%Y/%m").try_build().unwrap();
+
+    write_daily_lines(&writer, &clock, 2);
+    let log_files: Vec<PathBuf> = writer
+        .log_files()
+        .unwrap()
+        .into_iter()
+        .map(|log_file| log_file.path)
+        .collect();
+    writer.shutdown();
+
+    assert_eq!(
+        relative_file_paths(dir.path()),
+        ["2021/03/foo_r2021-03-31.log", "2021/04/foo_r2021-04-01.log"]
+    );
+    assert_eq!(
+        log_files,
+        [
+            dir.path().join("2021/03/foo_r2021-03-31.log"),
+            dir.path().join("2021/04/foo_r2021-04-01.log")
+        ]
+    );
+}
+
+#[test]
+fn retention_removes_nested_files_and_empty_subdirectories() {
+    let dir = tempfile::tempdir().unwrap();
+    let clock = MockClock::new(Utc.with_ymd_and_hms(2021, 3, 30, 12, 0, 0).unwrap());
+    let writer = builder(dir.path(), &clock, "%Y/%m/%d")
+        .keep_files(2)
+        .try_build()
+        .unwrap();
+
+    write_daily_lines(&writer, &clock, 4);
+    writer.shutdown();
+
+    assert_eq!(
+        relative_file_paths(dir.path()),
+        [
+            "2021/04/01/foo_r2021-04-01.log",
+            "2021/04/02/foo_r2021-04-02.log"
+        ]
+    );
+    // the directory of March became empty and was removed
+    assert!(!dir.path().join("2021/03").exists());
+}
+
+#[test]
+fn separators_within_specifiers_are_found_for_the_retention() {
+    let dir = tempfile::tempdir().unwrap();
+    let clock = MockClock::new(Utc.with_ymd_and_hms(2021, 3, 28, 12, 0, 0).unwrap());
+    // %D is %m/%d/%y
+    let writer = builder(dir.path(), &clock, "%D")
+        .keep_files(1)
+        .try_build()
+        .unwrap();
+
+    write_daily_lines(&writer, &clock, 2);
+    writer.shutdown();
+    assert_eq!(writer.log_files().unwrap().len(), 1);
+
+    assert_eq!(
+        relative_file_paths(dir.path()),
+        ["03/29/21/foo_r2021-03-29.log"]
+    );
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn symlink_points_to_the_nested_file() {
+    let dir = tempfile::tempdir().unwrap();
+    let link = dir.path().join("current.log");
+    let clock = MockClock::new(Utc.with_ymd_and_hms(2021, 3, 28, 12, 0, 0).unwrap());
+    let writer = builder(dir.path(), &clock, "%Y/%m/%d")
+        .create_symlink(&link)
+        .try_build()
+        .unwrap();
+
+    write_daily_lines(&writer, &clock, 2);
+    writer.shutdown();
+
+    assert_eq!(
+        std::fs::read_link(&link).unwrap(),
+        dir.path().join("2021/03/29/foo_r2021-03-29.log")
+    );
+    assert!(std::fs::read_to_string(&link).unwrap().contains("day 1"));
+}